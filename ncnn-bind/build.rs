@@ -302,10 +302,20 @@ fn main() {
     };
 
     let c_api_header = search_include(&include_paths, "c_api.h");
+    let c_api_dir = PathBuf::from(&c_api_header).parent().unwrap().to_path_buf();
+
+    // Accessors the C API lacks, compiled against the headers of the linked ncnn.
+    println!("cargo:rerun-if-changed=shim");
+    cc::Build::new()
+        .cpp(true)
+        .file("shim/shim.cpp")
+        .include(&c_api_dir)
+        .compile("ncnn_bind_shim");
 
     // Generate the bindings
     let bindings = bindgen::Builder::default()
-        .header(c_api_header)
+        .header("shim/shim.h")
+        .clang_arg(format!("-I{}", c_api_dir.display()))
         .allowlist_type("regex")
         .allowlist_function("ncnn.*")
         .allowlist_var("NCNN.*")
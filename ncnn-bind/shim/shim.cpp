@@ -0,0 +1,51 @@
+#include "shim.h"
+
+#include "mat.h"
+#include "option.h"
+
+// ncnn_option_t is an ncnn::Option allocated by ncnn_option_create.
+#define NCNN_BIND_OPTION_INT(field)                                         \
+    int ncnn_bind_option_get_##field(const ncnn_option_t opt)               \
+    {                                                                       \
+        return ((const ncnn::Option*)opt)->field;                           \
+    }                                                                       \
+    void ncnn_bind_option_set_##field(ncnn_option_t opt, int value)         \
+    {                                                                       \
+        ((ncnn::Option*)opt)->field = value;                                \
+    }
+
+#define NCNN_BIND_OPTION_BOOL(field)                                        \
+    int ncnn_bind_option_get_##field(const ncnn_option_t opt)               \
+    {                                                                       \
+        return ((const ncnn::Option*)opt)->field ? 1 : 0;                   \
+    }                                                                       \
+    void ncnn_bind_option_set_##field(ncnn_option_t opt, int enabled)       \
+    {                                                                       \
+        ((ncnn::Option*)opt)->field = enabled != 0;                         \
+    }
+
+extern "C" {
+
+NCNN_BIND_OPTION_BOOL(lightmode)
+NCNN_BIND_OPTION_INT(openmp_blocktime)
+NCNN_BIND_OPTION_INT(flush_denormals)
+NCNN_BIND_OPTION_BOOL(use_winograd_convolution)
+NCNN_BIND_OPTION_BOOL(use_sgemm_convolution)
+NCNN_BIND_OPTION_BOOL(use_int8_inference)
+NCNN_BIND_OPTION_BOOL(use_bf16_storage)
+NCNN_BIND_OPTION_BOOL(use_fp16_packed)
+NCNN_BIND_OPTION_BOOL(use_fp16_storage)
+NCNN_BIND_OPTION_BOOL(use_fp16_arithmetic)
+NCNN_BIND_OPTION_BOOL(use_int8_packed)
+NCNN_BIND_OPTION_BOOL(use_int8_storage)
+NCNN_BIND_OPTION_BOOL(use_int8_arithmetic)
+NCNN_BIND_OPTION_BOOL(use_packing_layout)
+
+// ncnn_mat_t is an ncnn::Mat allocated by the ncnn_mat_create* functions.
+int ncnn_bind_mat_get_refcount(const ncnn_mat_t mat)
+{
+    const ncnn::Mat* m = (const ncnn::Mat*)mat;
+    return m->refcount ? NCNN_XADD(m->refcount, 0) : 0;
+}
+
+} // extern "C"
@@ -0,0 +1,49 @@
+/* Accessors missing from the ncnn C API, implemented against the C++ headers of the ncnn
+ * version being linked so that no field layout is assumed on the Rust side. */
+
+#ifndef NCNN_BIND_SHIM_H
+#define NCNN_BIND_SHIM_H
+
+#include "c_api.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+int ncnn_bind_option_get_lightmode(const ncnn_option_t opt);
+void ncnn_bind_option_set_lightmode(ncnn_option_t opt, int lightmode);
+int ncnn_bind_option_get_openmp_blocktime(const ncnn_option_t opt);
+void ncnn_bind_option_set_openmp_blocktime(ncnn_option_t opt, int openmp_blocktime);
+int ncnn_bind_option_get_flush_denormals(const ncnn_option_t opt);
+void ncnn_bind_option_set_flush_denormals(ncnn_option_t opt, int flush_denormals);
+int ncnn_bind_option_get_use_winograd_convolution(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_winograd_convolution(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_sgemm_convolution(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_sgemm_convolution(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_int8_inference(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_int8_inference(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_bf16_storage(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_bf16_storage(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_fp16_packed(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_fp16_packed(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_fp16_storage(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_fp16_storage(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_fp16_arithmetic(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_fp16_arithmetic(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_int8_packed(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_int8_packed(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_int8_storage(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_int8_storage(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_int8_arithmetic(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_int8_arithmetic(ncnn_option_t opt, int enabled);
+int ncnn_bind_option_get_use_packing_layout(const ncnn_option_t opt);
+void ncnn_bind_option_set_use_packing_layout(ncnn_option_t opt, int enabled);
+
+/* Number of matrices sharing the data, 0 when it is external or empty. */
+int ncnn_bind_mat_get_refcount(const ncnn_mat_t mat);
+
+#ifdef __cplusplus
+} /* extern "C" */
+#endif
+
+#endif /* NCNN_BIND_SHIM_H */
@@ -8,7 +8,7 @@ fn param_path() -> std::path::PathBuf {
     std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
 }
 
-fn benchmark(name: &str, mut mat_in: Mat, opt: &ncnn_option, out: &str) -> anyhow::Result<()> {
+fn benchmark(name: &str, mut mat_in: Mat, opt: &ncnn_option) -> anyhow::Result<()> {
     let mut mat_out = Mat::new();
     mat_in.fill(1.0 as f32);
 
@@ -25,17 +25,26 @@ fn benchmark(name: &str, mut mat_in: Mat, opt: &ncnn_option, out: &str) -> anyho
         .set_model_datareader(dr)
         .build()?;
 
+    let input = net.input_names().into_iter().next();
+    let output = net.output_names().into_iter().next();
+    let (input, output) = match (input, output) {
+        (Some(input), Some(output)) => (input, output),
+        _ => anyhow::bail!("no input or output blob in {}", name),
+    };
+
     // warmup
-    let mut ex_warmup = net.create_extractor();
-    ex_warmup.input("data", &mat_in)?;
-    ex_warmup.extract(out, &mut mat_out)?;
+    {
+        let mut ex_warmup = net.create_extractor();
+        ex_warmup.input(&input, &mat_in)?;
+        ex_warmup.extract(&output, &mut mat_out)?;
+    }
 
     let loop_cnt = 10;
     let now = time::Instant::now();
     for _ in 0..loop_cnt {
         let mut ex = net.create_extractor();
-        ex.input("data", &mat_in)?;
-        ex.extract(out, &mut mat_out)?;
+        ex.input(&input, &mat_in)?;
+        ex.extract(&output, &mut mat_out)?;
     }
     let duration = now.elapsed().as_millis() / loop_cnt;
     println!("{} \t\t {} ms", name, duration);
@@ -48,223 +57,116 @@ fn main() -> anyhow::Result<()> {
     // opt.set_num_threads(8);
     // opt.set_vulkan_compute(false);
 
-    benchmark(
-        "squeezenet.param",
-        Mat::new_3d(227, 227, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("squeezenet.param", Mat::new_3d(227, 227, 3, None)?, &opt)?;
 
     benchmark(
         "squeezenet_int8.param",
         Mat::new_3d(227, 227, 3, None)?,
         &opt,
-        "output",
     )?;
 
-    benchmark(
-        "mobilenet.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("mobilenet.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
     benchmark(
         "mobilenet_int8.param",
         Mat::new_3d(224, 224, 3, None)?,
         &opt,
-        "output",
     )?;
 
-    benchmark(
-        "mobilenet_v2.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("mobilenet_v2.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "mobilenet_v3.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("mobilenet_v3.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "shufflenet.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("shufflenet.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "shufflenet_v2.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("shufflenet_v2.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "mnasnet.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("mnasnet.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
     benchmark(
         "proxylessnasnet.param",
         Mat::new_3d(224, 224, 3, None)?,
         &opt,
-        "output",
     )?;
 
     benchmark(
         "efficientnet_b0.param",
         Mat::new_3d(224, 224, 3, None)?,
         &opt,
-        "output",
     )?;
 
-    // benchmark("efficientnetv2_b0.param", Mat::new_3d(224, 224, 3, None), &opt, "output")?;
+    // benchmark("efficientnetv2_b0.param", Mat::new_3d(224, 224, 3, None), &opt)?;
 
-    benchmark(
-        "regnety_400m.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("regnety_400m.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "blazeface.param",
-        Mat::new_3d(128, 128, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("blazeface.param", Mat::new_3d(128, 128, 3, None)?, &opt)?;
 
-    benchmark(
-        "googlenet.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("googlenet.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
     benchmark(
         "googlenet_int8.param",
         Mat::new_3d(224, 224, 3, None)?,
         &opt,
-        "output",
     )?;
 
-    benchmark(
-        "resnet18.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("resnet18.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "resnet18_int8.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("resnet18_int8.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "alexnet.param",
-        Mat::new_3d(227, 227, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("alexnet.param", Mat::new_3d(227, 227, 3, None)?, &opt)?;
 
-    benchmark(
-        "vgg16.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("vgg16.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "vgg16_int8.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("vgg16_int8.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "resnet50.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("resnet50.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
-    benchmark(
-        "resnet50_int8.param",
-        Mat::new_3d(224, 224, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("resnet50_int8.param", Mat::new_3d(224, 224, 3, None)?, &opt)?;
 
     benchmark(
         "squeezenet_ssd.param",
         Mat::new_3d(300, 300, 3, None)?,
         &opt,
-        "output",
     )?;
 
     benchmark(
         "squeezenet_ssd_int8.param",
         Mat::new_3d(300, 300, 3, None)?,
         &opt,
-        "output",
     )?;
 
-    benchmark(
-        "mobilenet_ssd.param",
-        Mat::new_3d(300, 300, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("mobilenet_ssd.param", Mat::new_3d(300, 300, 3, None)?, &opt)?;
 
     benchmark(
         "mobilenet_ssd_int8.param",
         Mat::new_3d(300, 300, 3, None)?,
         &opt,
-        "output",
     )?;
 
     benchmark(
         "mobilenet_yolo.param",
         Mat::new_3d(416, 416, 3, None)?,
         &opt,
-        "output",
     )?;
 
     benchmark(
         "mobilenetv2_yolov3.param",
         Mat::new_3d(352, 352, 3, None)?,
         &opt,
-        "output",
     )?;
 
-    benchmark(
-        "yolov4-tiny.param",
-        Mat::new_3d(416, 416, 3, None)?,
-        &opt,
-        "output",
-    )?;
+    benchmark("yolov4-tiny.param", Mat::new_3d(416, 416, 3, None)?, &opt)?;
 
     benchmark(
         "nanodet-plus-m_416.param",
         Mat::new_3d(416, 416, 3, None)?,
         &opt,
-        "output",
     )?;
 
     benchmark(
         "nanodet-plus-m_416-int8.param",
         Mat::new_3d(416, 416, 3, None)?,
         &opt,
-        "output",
     )?;
 
     Ok(())
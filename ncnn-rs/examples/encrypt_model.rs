@@ -0,0 +1,64 @@
+use ncnn_rs::crypt::{encrypt, KEY_LEN, NONCE_LEN};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// Environment variable holding the hex key, used when no key file is given.
+const KEY_VAR: &str = "NCNN_MODEL_KEY";
+
+const USAGE: &str = "usage: encrypt_model [--key-file <key file>] <model.param> [model.bin]";
+
+fn parse_key(hex: &str) -> anyhow::Result<[u8; KEY_LEN]> {
+    let hex = hex.trim();
+    if hex.len() != KEY_LEN * 2 || !hex.is_ascii() {
+        anyhow::bail!("key must be {} hex digits", KEY_LEN * 2);
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(key)
+}
+
+/// Encrypts a `.param`/`.bin` pair into `.param.enc`/`.bin.enc` for `DataReader::from_encrypted`.
+///
+/// The hex key is read from the file given with `--key-file`, or from `NCNN_MODEL_KEY`, so that
+/// it does not show up in the shell history or the process list.
+fn main() -> anyhow::Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let key = if args.first().map(String::as_str) == Some("--key-file") {
+        let key_path = args.get(1).ok_or_else(|| anyhow::anyhow!(USAGE))?;
+        let key = std::fs::read_to_string(key_path)?;
+        args.drain(..2);
+        key
+    } else {
+        std::env::var(KEY_VAR).map_err(|_| anyhow::anyhow!("{} is not set", KEY_VAR))?
+    };
+    if args.is_empty() {
+        anyhow::bail!(USAGE);
+    }
+
+    let key = parse_key(&key)?;
+
+    for path in &args {
+        let path = Path::new(path);
+        let out_path = path.with_extension(format!(
+            "{}.enc",
+            path.extension().and_then(|e| e.to_str()).unwrap_or("")
+        ));
+
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce)?;
+
+        encrypt(
+            BufReader::new(File::open(path)?),
+            BufWriter::new(File::create(&out_path)?),
+            &key,
+            &nonce,
+        )?;
+
+        println!("{:?}", out_path);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use ncnn_rs::param::{Graph, ModelStats, Shape, WeightPrecision};
+use std::path::PathBuf;
+
+const MIB: f64 = (1 << 20) as f64;
+
+fn print_layers(graph: &Graph, stats: &ModelStats) {
+    println!(
+        "  {:<24} {:<32} {:>12} {:>12}",
+        "type", "name", "params", "MACs"
+    );
+    for layer in &stats.layers {
+        let macs = match layer.macs {
+            Some(macs) => macs.to_string(),
+            None => "?".to_string(),
+        };
+        let l = &graph.layers[layer.layer];
+        println!(
+            "  {:<24} {:<32} {:>12} {:>12}",
+            l.type_name,
+            l.name,
+            layer.params(),
+            macs
+        );
+    }
+}
+
+fn parse_shape(arg: &str) -> anyhow::Result<Shape> {
+    let sizes = arg
+        .split('x')
+        .map(|s| s.parse())
+        .collect::<Result<Vec<i32>, _>>()?;
+    match *sizes.as_slice() {
+        [w] => Ok(Shape::new_1d(w)),
+        [w, h] => Ok(Shape::new_2d(w, h)),
+        [w, h, c] => Ok(Shape::new_3d(w, h, c)),
+        _ => anyhow::bail!("invalid input shape `{}`", arg),
+    }
+}
+
+/// Prints parameter counts, compute and memory estimates of `.param` files, by default every
+/// file of the `params` directory. `--layers` adds a table of the layers of each file and
+/// `--input WxHxC` sets the shape of inputs without a declared one.
+fn main() -> anyhow::Result<()> {
+    let mut layers = false;
+    let mut input = None;
+    let mut paths = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--layers" => layers = true,
+            "--input" => match args.next() {
+                Some(shape) => input = Some(parse_shape(&shape)?),
+                None => {
+                    anyhow::bail!("usage: model_stats [--layers] [--input WxHxC] [model.param...]")
+                }
+            },
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../params");
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "param") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+    }
+
+    println!(
+        "{:<28} {:>7} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "model", "layers", "params M", "MACs M", "fp32 MiB", "fp16 MiB", "int8 MiB", "peak MiB"
+    );
+    for path in paths {
+        let graph = Graph::from_path(&path)?;
+        // Inputs without params have no declared shape.
+        let inputs: Vec<_> = graph
+            .layers
+            .iter()
+            .filter(|l| l.type_name == "Input" && l.params.is_empty())
+            .flat_map(|l| &l.tops)
+            .filter_map(|&blob| Some((graph.blobs[blob].name.as_str(), input?)))
+            .collect();
+        let stats = graph.model_stats(&inputs)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!(
+            "{:<28} {:>7} {:>10.2} {:>10.1} {:>10.2} {:>10.2} {:>10.2} {:>10.2}{}",
+            name,
+            graph.layers.len(),
+            stats.params() as f64 / 1e6,
+            stats.macs() as f64 / 1e6,
+            stats.weight_bytes(WeightPrecision::Float32) as f64 / MIB,
+            stats.weight_bytes(WeightPrecision::Float16) as f64 / MIB,
+            stats.weight_bytes(WeightPrecision::Int8) as f64 / MIB,
+            stats.peak_activation_bytes as f64 / MIB,
+            match stats.unknown_blobs {
+                0 => String::new(),
+                n => format!(" ({} blobs of unknown shape)", n),
+            }
+        );
+        if layers {
+            print_layers(&graph, &stats);
+        }
+    }
+
+    Ok(())
+}
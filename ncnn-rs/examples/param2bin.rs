@@ -0,0 +1,28 @@
+use ncnn_rs::param::Graph;
+use std::path::PathBuf;
+
+/// Converts a text `.param` into `.param.bin` and writes the matching `.id.rs` index table.
+fn main() -> anyhow::Result<()> {
+    let path = match std::env::args().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => anyhow::bail!("usage: param2bin <model.param>"),
+    };
+
+    let graph = Graph::from_path(&path)?;
+
+    let bin_path = path.with_extension("param.bin");
+    std::fs::write(&bin_path, graph.to_bin()?)?;
+
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("model")
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        .to_lowercase();
+    let id_path = path.with_extension("id.rs");
+    std::fs::write(&id_path, graph.id_table(&format!("{}_param_id", stem)))?;
+
+    println!("{:?}\n{:?}", bin_path, id_path);
+
+    Ok(())
+}
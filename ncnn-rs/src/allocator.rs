@@ -1,41 +1,214 @@
 use ncnn_bind::*;
+use std::collections::BTreeMap;
+use std::os::raw::c_void;
+use std::ptr::NonNull;
+use std::sync::{Arc, RwLock};
 
+/// Bytes ncnn may read past the end of an allocation with SIMD loads.
+const MALLOC_OVERREAD: usize = 64;
+
+/// Memory source for ncnn, turned into an [Allocator] with [Allocator::from_custom].
+///
+/// Allows routing ncnn memory into an arena, tracking usage or enforcing a budget.
+///
+/// # Safety
+///
+/// ncnn uses the memory returned by [NcnnAllocator::alloc] without any check. A non-null pointer
+/// must be aligned to 64 bytes, valid for reads and writes of the `size` bytes asked for, and
+/// must not overlap any other allocation until it is passed to [NcnnAllocator::free].
+pub unsafe trait NcnnAllocator: Send + Sync {
+    /// Allocates `size` bytes, aligned to 64 bytes to suit every SIMD extension ncnn may use.
+    ///
+    /// `size` already includes padding ncnn may read past the end of data. Returning null fails
+    /// the allocation, matrices then report [crate::Error::Allocation].
+    fn alloc(&self, size: usize) -> *mut u8;
+
+    /// Frees memory returned by [NcnnAllocator::alloc].
+    fn free(&self, ptr: NonNull<u8>);
+}
+
+// ncnn hands callbacks the C allocator struct only, custom allocators are found by its address.
+static CUSTOM: RwLock<BTreeMap<usize, usize>> = RwLock::new(BTreeMap::new());
+
+fn custom_for(allocator: ncnn_allocator_t) -> Option<*const Box<dyn NcnnAllocator>> {
+    CUSTOM
+        .read()
+        .unwrap()
+        .get(&(allocator as usize))
+        .map(|a| *a as *const Box<dyn NcnnAllocator>)
+}
+
+unsafe extern "C" fn custom_malloc(allocator: ncnn_allocator_t, size: size_t) -> *mut c_void {
+    match custom_for(allocator) {
+        Some(custom) => (*custom).alloc(size as usize + MALLOC_OVERREAD) as *mut c_void,
+        None => core::ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn custom_free(allocator: ncnn_allocator_t, ptr: *mut c_void) {
+    if let (Some(custom), Some(ptr)) = (custom_for(allocator), NonNull::new(ptr as *mut u8)) {
+        (*custom).free(ptr);
+    }
+}
+
+/// Allocator for matrix and network memory, either an ncnn pool or a custom [NcnnAllocator].
+///
+/// Cloning is cheap and shares the same pool. Matrices, options and networks using the allocator
+/// keep a clone of it, so the pool is only destroyed once nothing refers to its memory anymore.
+#[derive(Clone)]
 pub struct Allocator {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
     ptr: ncnn_allocator_t,
+    custom: Option<Box<Box<dyn NcnnAllocator>>>,
 }
 
+// Pool allocators are only ever reached through their C++ object, the locked one guards it with a
+// mutex and the unlocked one is documented as not shareable across threads. Custom allocators
+// are Send + Sync themselves.
+unsafe impl Send for Inner {}
+unsafe impl Sync for Inner {}
+
 impl Allocator {
     /// Creates a new pool allocator.
-    ///
-    /// # Safety
-    ///
-    /// Using the allocator when creating matrix results in a segmentation fault.
-    pub unsafe fn new() -> crate::allocator::Allocator {
-        Allocator {
-            ptr: ncnn_allocator_create_pool_allocator(),
-        }
+    pub fn new() -> crate::allocator::Allocator {
+        unsafe { Self::from_ptr(ncnn_allocator_create_pool_allocator()) }
     }
 
     /// Creates a new unlocked pool allocator.
     ///
     /// # Safety
     ///
-    /// Using the allocator when creating matrix results in a segmentation fault.
+    /// The allocator does not synchronize its pool, it must not be used by several threads at
+    /// the same time, e.g. as blob allocator of extractors running concurrently.
     pub unsafe fn new_unlocked() -> crate::allocator::Allocator {
+        Self::from_ptr(ncnn_allocator_create_unlocked_pool_allocator())
+    }
+
+    /// Creates an allocator taking its memory from `allocator`.
+    pub fn from_custom(allocator: impl NcnnAllocator + 'static) -> crate::allocator::Allocator {
+        let custom: Box<Box<dyn NcnnAllocator>> = Box::new(Box::new(allocator));
+
+        // The pool only provides the C++ object, every allocation goes through the callbacks.
+        let ptr = unsafe {
+            let ptr = ncnn_allocator_create_unlocked_pool_allocator();
+            (*ptr).fast_malloc = Some(custom_malloc);
+            (*ptr).fast_free = Some(custom_free);
+            ptr
+        };
+
+        CUSTOM.write().unwrap().insert(
+            ptr as usize,
+            &*custom as *const Box<dyn NcnnAllocator> as usize,
+        );
+
+        Allocator {
+            inner: Arc::new(Inner {
+                ptr,
+                custom: Some(custom),
+            }),
+        }
+    }
+
+    unsafe fn from_ptr(ptr: ncnn_allocator_t) -> Self {
         Allocator {
-            ptr: ncnn_allocator_create_unlocked_pool_allocator(),
+            inner: Arc::new(Inner { ptr, custom: None }),
         }
     }
 
     pub(crate) fn ptr(&self) -> ncnn_allocator_t {
-        self.ptr
+        self.inner.ptr
+    }
+
+    /// Whether both handles share the same pool.
+    pub(crate) fn same(&self, other: &Allocator) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
     }
 }
 
-impl Drop for Allocator {
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Inner {
     fn drop(&mut self) {
+        if self.custom.is_some() {
+            CUSTOM.write().unwrap().remove(&(self.ptr as usize));
+        }
         unsafe {
             ncnn_allocator_destroy(self.ptr);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Counting {
+        live: Mutex<HashMap<usize, Layout>>,
+        peak: Mutex<usize>,
+    }
+
+    unsafe impl NcnnAllocator for Arc<Counting> {
+        fn alloc(&self, size: usize) -> *mut u8 {
+            let layout = Layout::from_size_align(size, 64).unwrap();
+            let ptr = unsafe { std::alloc::alloc(layout) };
+            let mut live = self.live.lock().unwrap();
+            live.insert(ptr as usize, layout);
+            let used: usize = live.values().map(Layout::size).sum();
+            let mut peak = self.peak.lock().unwrap();
+            *peak = used.max(*peak);
+            ptr
+        }
+
+        fn free(&self, ptr: NonNull<u8>) {
+            let layout = self.live.lock().unwrap().remove(&(ptr.as_ptr() as usize));
+            unsafe { std::alloc::dealloc(ptr.as_ptr(), layout.unwrap()) };
+        }
+    }
+
+    #[test]
+    fn custom_allocator() {
+        let counting = Arc::new(Counting::default());
+        let alloc = Allocator::from_custom(counting.clone());
+
+        let mut m = crate::Mat::new_2d(16, 16, Some(&alloc)).unwrap();
+        drop(alloc);
+        m.fill(1.0);
+        assert_eq!(1, counting.live.lock().unwrap().len());
+        assert!(*counting.peak.lock().unwrap() >= 16 * 16 * 4);
+
+        drop(m);
+        assert!(counting.live.lock().unwrap().is_empty());
+    }
+
+    struct Exhausted;
+
+    unsafe impl NcnnAllocator for Exhausted {
+        fn alloc(&self, _size: usize) -> *mut u8 {
+            core::ptr::null_mut()
+        }
+
+        fn free(&self, _ptr: NonNull<u8>) {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn custom_allocator_failure() {
+        let alloc = Allocator::from_custom(Exhausted);
+        assert!(matches!(
+            crate::Mat::new_1d(4, Some(&alloc)),
+            Err(crate::Error::Allocation)
+        ));
+    }
+}
@@ -0,0 +1,149 @@
+//! ChaCha20 encryption of params and models, read back with [DataReader::from_encrypted].
+
+use crate::datareader::DataReader;
+use crate::error::{ensure, Error, Result};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use std::io::{Read, Write};
+
+/// Length in bytes of the ChaCha20 key.
+pub const KEY_LEN: usize = 32;
+/// Length in bytes of the ChaCha20 nonce stored in the header of encrypted files.
+pub const NONCE_LEN: usize = 12;
+
+/// Magic bytes starting every encrypted file.
+const MAGIC: &[u8; 4] = b"NCNE";
+
+/// [Read] adapter decrypting a stream produced by [encrypt].
+///
+/// The stream starts with a header holding the nonce, the rest is ChaCha20 ciphertext decrypted
+/// as it is read. The cipher is not authenticated, a wrong key yields garbage rather than an error.
+pub struct DecryptReader<R> {
+    inner: R,
+    cipher: ChaCha20,
+}
+
+impl<R: Read> DecryptReader<R> {
+    /// Reads the header of `inner` and prepares decryption with `key`.
+    pub fn new(mut inner: R, key: &[u8; KEY_LEN]) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+        ensure!(
+            &magic == MAGIC,
+            Error::InvalidArgument("Not an encrypted ncnn file".into())
+        );
+
+        let mut nonce = [0u8; NONCE_LEN];
+        inner.read_exact(&mut nonce)?;
+
+        Ok(Self {
+            inner,
+            cipher: ChaCha20::new(key.into(), &nonce.into()),
+        })
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.cipher.apply_keystream(&mut buf[..len]);
+        Ok(len)
+    }
+}
+
+/// Encrypts `reader` into `writer` with `key`, in the format read by [DecryptReader].
+///
+/// `nonce` must never be reused with the same key.
+pub fn encrypt(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+) -> Result<()> {
+    let mut cipher = ChaCha20::new(key.into(), nonce.into());
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(nonce)?;
+
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        cipher.apply_keystream(&mut buf[..len]);
+        writer.write_all(&buf[..len])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+impl DataReader {
+    /// Creates a [DataReader] decrypting a file produced by [encrypt] as ncnn reads it.
+    ///
+    /// Works for both text params and model weights, the plaintext is never stored whole.
+    pub fn from_encrypted(reader: impl Read + Send + 'static, key: &[u8; KEY_LEN]) -> Result<Self> {
+        Ok(Self::from_reader(DecryptReader::new(reader, key)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const KEY: [u8; KEY_LEN] = [7; KEY_LEN];
+    const NONCE: [u8; NONCE_LEN] = [3; NONCE_LEN];
+
+    fn encrypted(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        encrypt(data, &mut out, &KEY, &NONCE).unwrap();
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let enc = encrypted(&data);
+        assert_eq!(4 + NONCE_LEN + data.len(), enc.len());
+        assert_ne!(&data[..64], &enc[4 + NONCE_LEN..][..64]);
+
+        let mut dec = Vec::new();
+        DecryptReader::new(Cursor::new(enc), &KEY)
+            .unwrap()
+            .read_to_end(&mut dec)
+            .unwrap();
+        assert_eq!(data, dec);
+    }
+
+    #[test]
+    fn reject_plaintext() {
+        assert!(DecryptReader::new(&b"7767517\n1 1\n"[..], &KEY).is_err());
+        assert!(DecryptReader::new(&b"NCNE"[..], &KEY).is_err());
+    }
+
+    #[test]
+    fn load_encrypted() {
+        let param = "7767517\n2 2\nInput data 0 1 data 0=4\nReLU relu 1 1 data output\n";
+        let mut net = crate::NetBuilder::new()
+            .set_param_datareader(
+                DataReader::from_encrypted(Cursor::new(encrypted(param.as_bytes())), &KEY).unwrap(),
+            )
+            .set_model_datareader(
+                DataReader::from_encrypted(Cursor::new(encrypted(&[])), &KEY).unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(-1.0);
+        let mut output = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!(&[0.0; 4], output.as_slice::<f32>().unwrap());
+    }
+}
@@ -1,5 +1,10 @@
 use libc::memset;
 use ncnn_bind::*;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::io::{BufRead, BufReader, Read};
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::sync::RwLock;
 
 pub type ScanFn = unsafe extern "C" fn(
     dr: ncnn_datareader_t,
@@ -30,8 +35,240 @@ unsafe extern "C" fn empty_read(
     size
 }
 
+/// Value returned by scan when input ends before any conversion, like `EOF` in C.
+const SCAN_EOF: c_int = -1;
+
+/// Buffered stream answering ncnn's scanf-style and raw read requests.
+struct Stream {
+    reader: BufReader<Box<dyn Read + Send>>,
+}
+
+impl Stream {
+    fn new(reader: Box<dyn Read + Send>) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.reader.fill_buf().ok().and_then(|b| b.first().copied())
+    }
+
+    fn bump(&mut self) {
+        self.reader.consume(1);
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.bump();
+        }
+    }
+
+    /// Consumes up to `width` bytes accepted by `accept`.
+    fn take_while(&mut self, width: usize, mut accept: impl FnMut(u8, &[u8]) -> bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        while out.len() < width {
+            match self.peek() {
+                Some(c) if accept(c, &out) => {
+                    out.push(c);
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+        out
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        while len < buf.len() {
+            match self.reader.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+        len
+    }
+
+    /// Minimal `fscanf` supporting the single conversion formats ncnn uses: `%d`, `%x`, `%f`,
+    /// `%s` and `%[...]` scansets, with optional widths, literals and whitespace.
+    ///
+    /// # Safety
+    ///
+    /// `p` must point to storage matching the conversion, string conversions need `width + 1` bytes.
+    unsafe fn scan(&mut self, format: &[u8], p: *mut c_void) -> c_int {
+        let mut assigned = 0;
+        let mut i = 0;
+
+        while i < format.len() {
+            let f = format[i];
+            i += 1;
+
+            if f.is_ascii_whitespace() {
+                self.skip_whitespace();
+                continue;
+            }
+
+            if f != b'%' || format.get(i) == Some(&b'%') {
+                if f == b'%' {
+                    i += 1;
+                }
+                match self.peek() {
+                    Some(c) if c == f => self.bump(),
+                    Some(_) => return assigned,
+                    None => return if assigned == 0 { SCAN_EOF } else { assigned },
+                }
+                continue;
+            }
+
+            let width_start = i;
+            while format.get(i).is_some_and(|c| c.is_ascii_digit()) {
+                i += 1;
+            }
+            let width = std::str::from_utf8(&format[width_start..i])
+                .ok()
+                .and_then(|w| w.parse::<usize>().ok())
+                .unwrap_or(usize::MAX);
+
+            let Some(&conversion) = format.get(i) else {
+                return assigned;
+            };
+            i += 1;
+
+            // Only a single destination pointer is ever provided.
+            if assigned == 1 {
+                return assigned;
+            }
+
+            if conversion != b'[' {
+                self.skip_whitespace();
+            }
+            if self.peek().is_none() {
+                return if assigned == 0 { SCAN_EOF } else { assigned };
+            }
+
+            match conversion {
+                b'd' | b'i' => {
+                    let text = self.take_while(width, |c, s| {
+                        c.is_ascii_digit() || (s.is_empty() && (c == b'-' || c == b'+'))
+                    });
+                    match std::str::from_utf8(&text)
+                        .ok()
+                        .and_then(|t| t.parse::<c_int>().ok())
+                    {
+                        Some(v) => *(p as *mut c_int) = v,
+                        None => return assigned,
+                    }
+                }
+                b'x' | b'X' => {
+                    let text = self.take_while(width, |c, _| c.is_ascii_hexdigit());
+                    match std::str::from_utf8(&text)
+                        .ok()
+                        .and_then(|t| c_uint::from_str_radix(t, 16).ok())
+                    {
+                        Some(v) => *(p as *mut c_uint) = v,
+                        None => return assigned,
+                    }
+                }
+                b'f' | b'e' | b'g' => {
+                    let text = self.take_while(width, |c, s| {
+                        c.is_ascii_digit()
+                            || c == b'.'
+                            || c == b'e'
+                            || c == b'E'
+                            || ((c == b'-' || c == b'+')
+                                && (s.is_empty() || matches!(s.last(), Some(b'e' | b'E'))))
+                    });
+                    match std::str::from_utf8(&text)
+                        .ok()
+                        .and_then(|t| t.parse::<f32>().ok())
+                    {
+                        Some(v) => *(p as *mut f32) = v,
+                        None => return assigned,
+                    }
+                }
+                b's' => {
+                    let text = self.take_while(width, |c, _| !c.is_ascii_whitespace());
+                    write_c_str(p, &text);
+                }
+                b'[' => {
+                    let negate = format.get(i) == Some(&b'^');
+                    if negate {
+                        i += 1;
+                    }
+                    let set_start = i;
+                    // A `]` right after the opening bracket is part of the set.
+                    if format.get(i) == Some(&b']') {
+                        i += 1;
+                    }
+                    while format.get(i).is_some_and(|c| *c != b']') {
+                        i += 1;
+                    }
+                    let set = &format[set_start..i.min(format.len())];
+                    i += 1;
+
+                    let text = self.take_while(width, |c, _| set.contains(&c) != negate);
+                    if text.is_empty() {
+                        return assigned;
+                    }
+                    write_c_str(p, &text);
+                }
+                _ => return assigned,
+            }
+
+            assigned += 1;
+        }
+
+        assigned
+    }
+}
+
+unsafe fn write_c_str(p: *mut c_void, text: &[u8]) {
+    let p = p as *mut u8;
+    core::ptr::copy_nonoverlapping(text.as_ptr(), p, text.len());
+    *p.add(text.len()) = 0;
+}
+
+/// Streams of readers created with [DataReader::from_reader], keyed by their ncnn datareader.
+///
+/// The `pthis` field of ncnn datareaders is taken by the C API, so the callbacks look their
+/// stream up here instead.
+static STREAMS: RwLock<BTreeMap<usize, usize>> = RwLock::new(BTreeMap::new());
+
+fn stream_for(dr: ncnn_datareader_t) -> Option<*mut Stream> {
+    STREAMS
+        .read()
+        .unwrap()
+        .get(&(dr as usize))
+        .map(|s| *s as *mut Stream)
+}
+
+unsafe extern "C" fn stream_scan(
+    dr: ncnn_datareader_t,
+    format: *const c_char,
+    p: *mut c_void,
+) -> c_int {
+    match stream_for(dr) {
+        Some(stream) => (*stream).scan(CStr::from_ptr(format).to_bytes(), p),
+        None => SCAN_EOF,
+    }
+}
+
+unsafe extern "C" fn stream_read(dr: ncnn_datareader_t, buf: *mut c_void, size: size_t) -> size_t {
+    match stream_for(dr) {
+        Some(stream) if size > 0 => {
+            let buf = core::slice::from_raw_parts_mut(buf as *mut u8, size as usize);
+            (*stream).read(buf) as size_t
+        }
+        _ => 0,
+    }
+}
+
 pub struct DataReader {
     ptr: ncnn_datareader_t,
+    stream: Option<Box<Stream>>,
 }
 
 impl DataReader {
@@ -43,9 +280,44 @@ impl DataReader {
     pub unsafe fn new() -> Self {
         Self {
             ptr: ncnn_datareader_create(),
+            stream: None,
+        }
+    }
+
+    /// Creates a [DataReader] reading params and model weights from any [Read] implementation.
+    ///
+    /// Text params are parsed with a scanf-like scanner covering the formats used by ncnn.
+    pub fn from_reader(reader: impl Read + Send + 'static) -> Self {
+        let mut stream = Box::new(Stream::new(Box::new(reader)));
+
+        let ptr = unsafe {
+            let ptr = ncnn_datareader_create();
+            (*ptr).scan = Some(stream_scan);
+            (*ptr).read = Some(stream_read);
+            ptr
+        };
+
+        STREAMS
+            .write()
+            .unwrap()
+            .insert(ptr as usize, &mut *stream as *mut Stream as usize);
+
+        Self {
+            ptr,
+            stream: Some(stream),
         }
     }
 
+    /// Creates a [DataReader] reading from a borrowed slice.
+    ///
+    /// # Safety
+    ///
+    /// `data` must outlive the returned [DataReader].
+    pub(crate) unsafe fn from_slice(data: &[u8]) -> Self {
+        let data: &'static [u8] = core::slice::from_raw_parts(data.as_ptr(), data.len());
+        Self::from_reader(data)
+    }
+
     /// Creates an empty [DataReader] that always reads zero bytes.
     pub fn empty() -> Self {
         Self {
@@ -55,6 +327,7 @@ impl DataReader {
                 (*ptr).read = Some(empty_read);
                 ptr
             },
+            stream: None,
         }
     }
 
@@ -73,6 +346,9 @@ impl DataReader {
 
 impl Drop for DataReader {
     fn drop(&mut self) {
+        if self.stream.take().is_some() {
+            STREAMS.write().unwrap().remove(&(self.ptr as usize));
+        }
         unsafe {
             ncnn_datareader_destroy(self.ptr);
         }
@@ -81,9 +357,110 @@ impl Drop for DataReader {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn stream(text: &str) -> Stream {
+        Stream::new(Box::new(Cursor::new(text.as_bytes().to_vec())))
+    }
+
+    fn scan_int(stream: &mut Stream, format: &[u8]) -> (c_int, c_int) {
+        let mut v: c_int = 0;
+        let n = unsafe { stream.scan(format, &mut v as *mut _ as _) };
+        (n, v)
+    }
+
+    fn scan_str(stream: &mut Stream, format: &[u8]) -> (c_int, String) {
+        let mut buf = [0u8; 256];
+        let n = unsafe { stream.scan(format, buf.as_mut_ptr() as _) };
+        let s = CStr::from_bytes_until_nul(&buf).unwrap();
+        (n, s.to_str().unwrap().to_string())
+    }
+
     #[test]
     fn empty_datareader() {
-        use crate::datareader::*;
         let _ = DataReader::empty();
     }
+
+    #[test]
+    fn scan_param_line() {
+        let mut s = stream("7767517\n1 1\nConvolution  conv1 1 1 data out 0=32 -23310=2,1.5,-2 1=1.000000e-01\nReLU");
+        assert_eq!((1, 7767517), scan_int(&mut s, b"%d"));
+        assert_eq!((1, 1), scan_int(&mut s, b"%d"));
+        assert_eq!((1, 1), scan_int(&mut s, b"%d"));
+        assert_eq!((1, "Convolution".into()), scan_str(&mut s, b"%255s"));
+        assert_eq!((1, "conv1".into()), scan_str(&mut s, b"%255s"));
+        assert_eq!((1, 1), scan_int(&mut s, b"%d"));
+        assert_eq!((1, 1), scan_int(&mut s, b"%d"));
+        assert_eq!((1, "data".into()), scan_str(&mut s, b"%255s"));
+        assert_eq!((1, "out".into()), scan_str(&mut s, b"%255s"));
+
+        assert_eq!((1, 0), scan_int(&mut s, b"%d="));
+        assert_eq!((1, "32".into()), scan_str(&mut s, b"%15s"));
+        assert_eq!((1, -23310), scan_int(&mut s, b"%d="));
+        assert_eq!((1, 2), scan_int(&mut s, b"%d"));
+        assert_eq!((1, "1.5".into()), scan_str(&mut s, b",%15[^,\n ]"));
+        assert_eq!((1, "-2".into()), scan_str(&mut s, b",%15[^,\n ]"));
+        assert_eq!((1, 1), scan_int(&mut s, b"%d="));
+
+        let mut f = 0f32;
+        assert_eq!(1, unsafe { s.scan(b"%f", &mut f as *mut _ as _) });
+        assert_eq!(0.1, f);
+
+        // The next layer type is not an integer and must be left in the stream.
+        assert_eq!(0, scan_int(&mut s, b"%d=").0);
+        assert_eq!((1, "ReLU".into()), scan_str(&mut s, b"%255s"));
+        assert_eq!(SCAN_EOF, scan_int(&mut s, b"%d=").0);
+    }
+
+    #[test]
+    fn scan_width_and_hex() {
+        let mut s = stream("abcdef ff");
+        assert_eq!((1, "abc".into()), scan_str(&mut s, b"%3s"));
+        assert_eq!((1, "def".into()), scan_str(&mut s, b"%s"));
+
+        let mut v: c_uint = 0;
+        assert_eq!(1, unsafe { s.scan(b"%x", &mut v as *mut _ as _) });
+        assert_eq!(0xff, v);
+    }
+
+    #[test]
+    fn scan_out_of_range() {
+        let mut s = stream("2147483648 -2147483649 100000000");
+        assert_eq!(0, scan_int(&mut s, b"%d").0);
+        assert_eq!(0, scan_int(&mut s, b"%d").0);
+
+        let mut v: c_uint = 0;
+        assert_eq!(0, unsafe { s.scan(b"%x", &mut v as *mut _ as _) });
+        assert_eq!(0, v);
+    }
+
+    #[test]
+    fn read_after_scan() {
+        let mut s = stream("12 \x01\x02\x03");
+        assert_eq!((1, 12), scan_int(&mut s, b"%d "));
+        let mut buf = [0u8; 4];
+        assert_eq!(3, s.read(&mut buf));
+        assert_eq!([1, 2, 3, 0], buf);
+    }
+
+    #[test]
+    fn load_from_reader() {
+        let param = "7767517\n2 2\nInput data 0 1 data 0=4\nReLU relu 1 1 data output\n";
+        let mut net = crate::NetBuilder::new()
+            .set_param_datareader(DataReader::from_reader(Cursor::new(
+                param.as_bytes().to_vec(),
+            )))
+            .set_model_datareader(DataReader::from_reader(std::io::empty()))
+            .build()
+            .unwrap();
+
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(-1.0);
+        let mut output = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!(&[0.0; 4], output.as_slice::<f32>().unwrap());
+    }
 }
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Errors returned by ncnn-rs.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Params could not be parsed, at a 1-based `line` of text params or a byte `offset` of
+    /// binary ones when known.
+    ParamParse {
+        line: Option<usize>,
+        offset: Option<usize>,
+        message: String,
+    },
+    /// ncnn failed to load params, `path` is set when they were read from a file.
+    ParamLoad { path: Option<PathBuf> },
+    /// ncnn failed to load params that [crate::param::Graph::validate] finds errors in, `path`
+    /// is set when they were read from a file.
+    InvalidParams {
+        path: Option<PathBuf>,
+        diagnostics: Vec<crate::param::Diagnostic>,
+    },
+    /// ncnn failed to load model weights, `path` is set when they were read from a file and
+    /// `offset` when the position reached is known.
+    ModelLoad {
+        path: Option<PathBuf>,
+        offset: Option<usize>,
+    },
+    /// No blob has the given name.
+    UnknownBlob(String),
+    /// No blob has the given index.
+    InvalidBlobIndex(usize),
+    /// The params graph of a [crate::Net] loaded from a [crate::DataReader] is not known, so
+    /// only its input and output blobs can be looked up.
+    GraphUnavailable,
+    /// ncnn failed computing a blob and returned `code`.
+    Extract { blob: String, code: i32 },
+    /// An ncnn layer run outside of a network failed and returned `code`.
+    Layer { layer: String, code: i32 },
+    /// The layer type is not known to ncnn.
+    UnknownLayerType(String),
+    /// Provided data does not match the expected shape or size.
+    ShapeMismatch(String),
+    /// The matrix element type, packing or memory layout does not allow the requested access.
+    Layout(String),
+    /// A dimension or index is out of the supported range.
+    InvalidDimension(String),
+    /// Memory could not be allocated.
+    Allocation,
+    /// An argument is invalid, e.g. a path with an interior nul byte.
+    InvalidArgument(String),
+    /// I/O failure, `path` is set when it concerns a file.
+    Io {
+        path: Option<PathBuf>,
+        source: std::io::Error,
+    },
+}
+
+/// Result type of ncnn-rs.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Param parse error without location yet.
+    pub(crate) fn param(message: impl Into<String>) -> Self {
+        Error::ParamParse {
+            line: None,
+            offset: None,
+            message: message.into(),
+        }
+    }
+
+    /// Param parse error at a byte offset of binary params.
+    pub(crate) fn param_at_offset(offset: usize, message: impl Into<String>) -> Self {
+        Error::ParamParse {
+            line: None,
+            offset: Some(offset),
+            message: message.into(),
+        }
+    }
+
+    /// Sets the line of a param parse error, other errors are returned unchanged.
+    pub(crate) fn with_line(self, line: usize) -> Self {
+        match self {
+            Error::ParamParse {
+                offset, message, ..
+            } => Error::ParamParse {
+                line: Some(line),
+                offset,
+                message,
+            },
+            e => e,
+        }
+    }
+
+    /// I/O error concerning the file at `path`.
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Error::Io {
+            path: Some(path.into()),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ParamParse {
+                line: Some(line),
+                message,
+                ..
+            } => write!(f, "line {}: {}", line, message),
+            Error::ParamParse {
+                offset: Some(offset),
+                message,
+                ..
+            } => write!(f, "offset {}: {}", offset, message),
+            Error::ParamParse { message, .. } => f.write_str(message),
+            Error::ParamLoad { path: Some(path) } => {
+                write!(f, "Error loading params from {:?}", path)
+            }
+            Error::ParamLoad { path: None } => f.write_str("Error loading params"),
+            Error::InvalidParams { path, diagnostics } => {
+                f.write_str("Invalid params")?;
+                if let Some(path) = path {
+                    write!(f, " from {:?}", path)?;
+                }
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    f.write_str(if i == 0 { ": " } else { "; " })?;
+                    diagnostic.fmt(f)?;
+                }
+                Ok(())
+            }
+            Error::ModelLoad { path, offset } => {
+                f.write_str("Error loading model")?;
+                if let Some(path) = path {
+                    write!(f, " from {:?}", path)?;
+                }
+                if let Some(offset) = offset {
+                    write!(f, " at offset {}", offset)?;
+                }
+                Ok(())
+            }
+            Error::UnknownBlob(name) => write!(f, "Unknown blob `{}`", name),
+            Error::InvalidBlobIndex(index) => write!(f, "Invalid blob index {}", index),
+            Error::GraphUnavailable => {
+                f.write_str("Params graph unavailable for a net loaded from a DataReader")
+            }
+            Error::Extract { blob, code } => {
+                write!(f, "Error running extract on blob `{}` ({})", blob, code)
+            }
+            Error::Layer { layer, code } => write!(f, "Error running layer `{}` ({})", layer, code),
+            Error::UnknownLayerType(name) => write!(f, "Unknown layer type `{}`", name),
+            Error::ShapeMismatch(message)
+            | Error::Layout(message)
+            | Error::InvalidDimension(message)
+            | Error::InvalidArgument(message) => f.write_str(message),
+            Error::Allocation => f.write_str("Memory allocation failed"),
+            Error::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "Error reading {:?}: {}", path, source),
+            Error::Io { path: None, source } => source.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(source: std::io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+/// Returns `$err` unless `$cond` holds.
+macro_rules! ensure {
+    ($cond:expr, $err:expr) => {
+        if !$cond {
+            return Err($err);
+        }
+    };
+}
+pub(crate) use ensure;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let err = Error::param("Invalid int value `x`").with_line(4);
+        assert_eq!("line 4: Invalid int value `x`", err.to_string());
+        assert_eq!(
+            "offset 8: Unexpected end of data",
+            Error::param_at_offset(8, "Unexpected end of data").to_string()
+        );
+        assert_eq!(
+            "Error loading model at offset 12",
+            Error::ModelLoad {
+                path: None,
+                offset: Some(12)
+            }
+            .to_string()
+        );
+        assert!(matches!(
+            Error::UnknownBlob("x".into()).with_line(1),
+            Error::UnknownBlob(_)
+        ));
+    }
+
+    #[test]
+    fn io_source() {
+        use std::error::Error as _;
+        let err = Error::io("model.bin", std::io::ErrorKind::NotFound.into());
+        assert!(err.source().is_some());
+        assert!(err.to_string().starts_with("Error reading \"model.bin\""));
+    }
+}
@@ -1,15 +1,21 @@
+use crate::allocator::Allocator;
+use crate::error::{Error, Result};
+use crate::net::BlobId;
 use ncnn_bind::*;
 use std::{ffi::CString, marker::PhantomData};
 
 pub struct Extractor<'a> {
     ptr: ncnn_extractor_t,
+    // Allocators of the network and extractor options, handed over to extracted matrices.
+    allocators: Vec<Allocator>,
     _phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Extractor<'a> {
-    pub(crate) fn from_ptr(ptr: ncnn_extractor_t) -> Self {
+    pub(crate) fn from_ptr(ptr: ncnn_extractor_t, allocators: Vec<Allocator>) -> Self {
         Self {
             ptr,
+            allocators,
             _phantom: PhantomData::default(),
         }
     }
@@ -17,27 +23,93 @@ impl<'a> Extractor<'a> {
     /// Sets extractor option.
     pub fn set_option(&mut self, opt: &crate::option::Option) {
         unsafe { ncnn_extractor_set_option(self.ptr, opt.ptr()) };
+        self.allocators.extend(opt.allocators());
+    }
+
+    /// Keeps the allocators of an input alive in the extracted matrices, which may share its data.
+    fn keep_input_allocators(&mut self, mat: &crate::mat::Mat) {
+        for alloc in mat.allocators() {
+            if !self.allocators.iter().any(|a| a.same(alloc)) {
+                self.allocators.push(alloc.clone());
+            }
+        }
     }
 
     /// Sets input tensor by a given name.
-    pub fn input(&mut self, name: &str, mat: &'a crate::mat::Mat) -> anyhow::Result<()> {
-        let c_str = CString::new(name).unwrap();
+    pub fn input(&mut self, name: &str, mat: &'a crate::mat::Mat) -> Result<()> {
+        let c_str = CString::new(name).map_err(|_| Error::UnknownBlob(name.to_string()))?;
         if unsafe { ncnn_extractor_input(self.ptr, c_str.as_ptr(), mat.ptr()) } != 0 {
-            anyhow::bail!("Error setting input for layer `{}`", name);
+            Err(Error::UnknownBlob(name.to_string()))
         } else {
+            self.keep_input_allocators(mat);
             Ok(())
         }
     }
 
-    /// Runs network inferrence and returns output tensor by a given name.
-    pub fn extract(self, name: &str, mat: &mut crate::mat::Mat) -> anyhow::Result<()> {
-        let c_str = CString::new(name).unwrap();
-        if unsafe { ncnn_extractor_extract(self.ptr, c_str.as_ptr(), mat.mut_ptr()) } != 0 {
-            anyhow::bail!("Error running extract on layer `{}`", name);
+    /// Sets input tensor by a blob id resolved with [crate::Net::blob_id].
+    pub fn input_index(&mut self, id: BlobId, mat: &'a crate::mat::Mat) -> Result<()> {
+        if unsafe { ncnn_extractor_input_index(self.ptr, id.0, mat.ptr()) } != 0 {
+            Err(Error::InvalidBlobIndex(id.index()))
         } else {
+            self.keep_input_allocators(mat);
             Ok(())
         }
     }
+
+    /// Runs network inferrence and returns output tensor by a given name.
+    ///
+    /// Blobs computed along the way are kept, so extracting further outputs from the same
+    /// extractor only runs the layers not computed yet.
+    pub fn extract(&mut self, name: &str, mat: &mut crate::mat::Mat) -> Result<()> {
+        let c_str = CString::new(name).map_err(|_| Error::UnknownBlob(name.to_string()))?;
+        match unsafe { ncnn_extractor_extract(self.ptr, c_str.as_ptr(), mat.mut_ptr()) } {
+            0 => {
+                mat.keep_allocators(&self.allocators);
+                Ok(())
+            }
+            code => Err(Error::Extract {
+                blob: name.to_string(),
+                code,
+            }),
+        }
+    }
+
+    /// Runs network inferrence and returns output tensor by a blob id resolved with
+    /// [crate::Net::blob_id].
+    pub fn extract_index(&mut self, id: BlobId, mat: &mut crate::mat::Mat) -> Result<()> {
+        match unsafe { ncnn_extractor_extract_index(self.ptr, id.0, mat.mut_ptr()) } {
+            0 => {
+                mat.keep_allocators(&self.allocators);
+                Ok(())
+            }
+            code => Err(Error::Extract {
+                blob: id.index().to_string(),
+                code,
+            }),
+        }
+    }
+
+    /// Runs network inferrence and returns the output tensors of all the given names, in order.
+    ///
+    /// Names must be distinct, as the tensors of a repeated name would share their data.
+    pub fn extract_many(&mut self, names: &[&str]) -> Result<Vec<crate::mat::Mat>> {
+        let repeated = (1..names.len()).find(|&i| names[..i].contains(&names[i]));
+        if let Some(i) = repeated {
+            return Err(Error::InvalidArgument(format!(
+                "Blob `{}` requested more than once",
+                names[i]
+            )));
+        }
+
+        names
+            .iter()
+            .map(|name| {
+                let mut mat = crate::mat::Mat::new();
+                self.extract(name, &mut mat)?;
+                Ok(mat)
+            })
+            .collect()
+    }
 }
 
 impl<'a> Drop for Extractor<'a> {
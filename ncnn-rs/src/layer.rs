@@ -1,11 +1,11 @@
+use crate::allocator::Allocator;
+use crate::error::{ensure, Error, Result};
+use crate::mat::Mat;
+use crate::modelbin::ModelBin;
+use crate::paramdict::ParamDict;
 use ncnn_bind::*;
 use std::ffi::{c_char, CStr, CString};
 
-pub enum LayerError {
-    BadTypeName,
-    UnknownType,
-}
-
 #[derive(Clone, Copy)]
 pub struct LayerId(i32);
 
@@ -19,6 +19,8 @@ pub struct LayerShape {
 
 pub struct Layer {
     ptr: ncnn_layer_t,
+    // Allocators of the options the layer ran with, which its pipeline may use.
+    allocators: Vec<Allocator>,
 }
 
 impl Layer {
@@ -28,32 +30,198 @@ impl Layer {
         str_slice
     }
 
-    pub fn create() -> Self {
+    fn from_ptr(ptr: ncnn_layer_t) -> Self {
         Self {
-            ptr: unsafe { ncnn_layer_create() },
+            ptr,
+            allocators: Vec::new(),
         }
     }
 
-    pub fn create_by_type_name<S: AsRef<str>>(type_name: S) -> Result<Self, LayerError> {
-        let c_str = CString::new(type_name.as_ref()).map_err(|_| LayerError::BadTypeName)?;
+    fn check_code(&self, code: i32) -> Result<()> {
+        if code == 0 {
+            Ok(())
+        } else {
+            Err(Error::Layer {
+                layer: self.type_name().to_string(),
+                code,
+            })
+        }
+    }
+
+    fn keep_allocators(&mut self, opt: &crate::option::Option) {
+        for alloc in opt.allocators() {
+            if !self.allocators.iter().any(|a| a.same(&alloc)) {
+                self.allocators.push(alloc);
+            }
+        }
+    }
+
+    pub fn create() -> Self {
+        Self::from_ptr(unsafe { ncnn_layer_create() })
+    }
+
+    pub fn create_by_type_name<S: AsRef<str>>(type_name: S) -> Result<Self> {
+        let type_name = type_name.as_ref();
+        let c_str = CString::new(type_name)
+            .map_err(|_| Error::InvalidArgument(format!("Invalid layer type `{}`", type_name)))?;
 
         let ptr = unsafe { ncnn_layer_create_by_type(c_str.as_ptr()) };
 
         if ptr.is_null() {
-            Err(LayerError::UnknownType)
+            Err(Error::UnknownLayerType(type_name.to_string()))
         } else {
-            Ok(Self { ptr })
+            Ok(Self::from_ptr(ptr))
         }
     }
 
-    pub fn create_by_type_id(id: LayerId) -> Result<Self, LayerError> {
+    pub fn create_by_type_id(id: LayerId) -> Result<Self> {
         let ptr = unsafe { ncnn_layer_create_by_typeindex(id.0) };
 
         if ptr.is_null() {
-            Err(LayerError::UnknownType)
+            Err(Error::UnknownLayerType(id.0.to_string()))
+        } else {
+            Ok(Self::from_ptr(ptr))
+        }
+    }
+
+    /// Loads layer parameters, e.g. converted from a [crate::param::ParamMap].
+    pub fn load_param(&mut self, pd: &ParamDict) -> Result<()> {
+        let load_param = unsafe { (*self.ptr).load_param };
+        match load_param.map(|load_param| unsafe { load_param(self.ptr, pd.ptr()) }) {
+            Some(0) => Ok(()),
+            _ => Err(Error::ParamLoad { path: None }),
+        }
+    }
+
+    /// Loads layer weights, after [Layer::load_param].
+    pub fn load_model(&mut self, mb: &ModelBin) -> Result<()> {
+        let load_model = unsafe { (*self.ptr).load_model };
+        match load_model.map(|load_model| unsafe { load_model(self.ptr, mb.ptr()) }) {
+            Some(0) => Ok(()),
+            _ => Err(Error::ModelLoad {
+                path: None,
+                offset: None,
+            }),
+        }
+    }
+
+    /// Prepares the layer for running with `opt`, once its params and weights are loaded.
+    pub fn create_pipeline(&mut self, opt: &crate::option::Option) -> Result<()> {
+        let create_pipeline = unsafe { (*self.ptr).create_pipeline };
+        let code = create_pipeline.map_or(-1, |f| unsafe { f(self.ptr, opt.ptr()) });
+        self.keep_allocators(opt);
+        self.check_code(code)
+    }
+
+    /// Releases what [Layer::create_pipeline] prepared.
+    pub fn destroy_pipeline(&mut self, opt: &crate::option::Option) -> Result<()> {
+        let destroy_pipeline = unsafe { (*self.ptr).destroy_pipeline };
+        let code = destroy_pipeline.map_or(-1, |f| unsafe { f(self.ptr, opt.ptr()) });
+        self.keep_allocators(opt);
+        self.check_code(code)
+    }
+
+    /// Runs the layer on `bottoms` and returns its output.
+    ///
+    /// Inputs must use the storage the layer expects with `opt`, e.g. fp32 data once fp16 and
+    /// bf16 storage are disabled. Layers with several outputs need [Layer::forward_n].
+    pub fn forward(&self, bottoms: &[&Mat], opt: &crate::option::Option) -> Result<Vec<Mat>> {
+        self.forward_n(bottoms, 1, opt)
+    }
+
+    /// Runs the layer on `bottoms` and returns its `tops` outputs, e.g. the parts of a Slice.
+    pub fn forward_n(
+        &self,
+        bottoms: &[&Mat],
+        tops: usize,
+        opt: &crate::option::Option,
+    ) -> Result<Vec<Mat>> {
+        let mut top_ptrs = vec![core::ptr::null_mut(); tops];
+        let code = if self.one_blob_only() {
+            ensure!(
+                bottoms.len() == 1 && tops == 1,
+                Error::InvalidArgument(format!(
+                    "Layer `{}` takes one input and one output",
+                    self.type_name()
+                ))
+            );
+            let forward = unsafe { (*self.ptr).forward_1 };
+            forward.map_or(-1, |f| unsafe {
+                f(self.ptr, bottoms[0].ptr(), &mut top_ptrs[0], opt.ptr())
+            })
+        } else {
+            let bottom_ptrs: Vec<_> = bottoms.iter().map(|mat| mat.ptr()).collect();
+            let n = i32::try_from(bottom_ptrs.len())
+                .map_err(|_| Error::InvalidArgument("Too many inputs".into()))?;
+            let n2 = i32::try_from(tops)
+                .map_err(|_| Error::InvalidArgument("Too many outputs".into()))?;
+            let forward = unsafe { (*self.ptr).forward_n };
+            forward.map_or(-1, |f| unsafe {
+                f(
+                    self.ptr,
+                    bottom_ptrs.as_ptr(),
+                    n,
+                    top_ptrs.as_mut_ptr(),
+                    n2,
+                    opt.ptr(),
+                )
+            })
+        };
+
+        // Outputs may share data with the inputs or be allocated with the option allocators.
+        let mut allocators = opt.allocators();
+        for mat in bottoms {
+            allocators.extend(mat.allocators().iter().cloned());
+        }
+        let tops: Vec<Mat> = top_ptrs
+            .into_iter()
+            .filter(|ptr| !ptr.is_null())
+            .map(|ptr| {
+                let mut mat = unsafe { Mat::from_raw(ptr) };
+                mat.keep_allocators(&allocators);
+                mat
+            })
+            .collect();
+        self.check_code(code)?;
+        Ok(tops)
+    }
+
+    /// Runs the layer in place on `bottom_tops`, for layers supporting it.
+    pub fn forward_inplace(
+        &self,
+        bottom_tops: &mut [Mat],
+        opt: &crate::option::Option,
+    ) -> Result<()> {
+        ensure!(
+            self.support_inplace(),
+            Error::InvalidArgument(format!("Layer `{}` cannot run in place", self.type_name()))
+        );
+        let code = if self.one_blob_only() {
+            ensure!(
+                bottom_tops.len() == 1,
+                Error::InvalidArgument(format!(
+                    "Layer `{}` takes one input and one output",
+                    self.type_name()
+                ))
+            );
+            let forward = unsafe { (*self.ptr).forward_inplace_1 };
+            forward.map_or(-1, |f| unsafe {
+                f(self.ptr, bottom_tops[0].ptr(), opt.ptr())
+            })
         } else {
-            Ok(Self { ptr })
+            let mut ptrs: Vec<_> = bottom_tops.iter().map(|mat| mat.ptr()).collect();
+            let n = i32::try_from(ptrs.len())
+                .map_err(|_| Error::InvalidArgument("Too many inputs".into()))?;
+            let forward = unsafe { (*self.ptr).forward_inplace_n };
+            forward.map_or(-1, |f| unsafe {
+                f(self.ptr, ptrs.as_mut_ptr(), n, opt.ptr())
+            })
+        };
+
+        for mat in bottom_tops {
+            mat.keep_allocators(&opt.allocators());
         }
+        self.check_code(code)
     }
 
     pub fn name(&self) -> &'static str {
@@ -222,3 +390,59 @@ impl Drop for Layer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mat;
+
+    #[test]
+    fn load_standalone() {
+        let mut layer = Layer::create_by_type_name("Scale").unwrap();
+        let mut pd = ParamDict::new();
+        pd.set_int(0, 2).unwrap().set_int(1, 1).unwrap();
+        layer.load_param(&pd).unwrap();
+
+        let mut scale = Mat::new_1d(2, None).unwrap();
+        scale.fill(2.0);
+        layer
+            .load_model(&ModelBin::from_mats([scale, Mat::new_1d(2, None).unwrap()]))
+            .unwrap();
+        // The bias is missing.
+        assert!(layer
+            .load_model(&ModelBin::from_mats([Mat::new_1d(2, None).unwrap()]))
+            .is_err());
+    }
+
+    #[test]
+    fn forward_standalone() {
+        let mut opt = crate::Option::new();
+        opt.set_fp16_storage(false);
+
+        let mut relu = Layer::create_by_type_name("ReLU").unwrap();
+        relu.load_param(&ParamDict::new()).unwrap();
+        relu.create_pipeline(&opt).unwrap();
+
+        let mut input = Mat::new_1d(4, None).unwrap();
+        input
+            .as_mut_slice::<f32>()
+            .unwrap()
+            .copy_from_slice(&[-1.0, 2.0, -3.0, 4.0]);
+        let output = relu.forward(&[&input], &opt).unwrap();
+        assert_eq!(&[0.0, 2.0, 0.0, 4.0], output[0].as_slice::<f32>().unwrap());
+        assert_eq!(&[-1.0, 2.0, -3.0, 4.0], input.as_slice::<f32>().unwrap());
+
+        let mut inputs = [input];
+        relu.forward_inplace(&mut inputs, &opt).unwrap();
+        assert_eq!(&[0.0, 2.0, 0.0, 4.0], inputs[0].as_slice::<f32>().unwrap());
+        assert!(relu.forward(&[&inputs[0], &inputs[0]], &opt).is_err());
+        relu.destroy_pipeline(&opt).unwrap();
+
+        let mut concat = Layer::create_by_type_name("Concat").unwrap();
+        concat.load_param(&ParamDict::new()).unwrap();
+        concat.create_pipeline(&opt).unwrap();
+        let output = concat.forward(&[&inputs[0], &inputs[0]], &opt).unwrap();
+        assert_eq!(8, output[0].width());
+        assert!(concat.forward_inplace(&mut inputs, &opt).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::allocator::Allocator;
+use crate::error::{Error, Result};
+use crate::mat::Mat;
+use crate::modelbin::ModelBin;
+use crate::paramdict::ParamDict;
+use ncnn_bind::*;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::os::raw::{c_int, c_void};
+use std::sync::{Mutex, RwLock};
+
+/// Layer implemented in Rust, registered on a network with
+/// [crate::NetBuilder::register_custom_layer].
+///
+/// Inputs are always unpacked fp32 matrices, ncnn converts them before calling the layer. A
+/// network may run the layer from several extractors at the same time.
+pub trait CustomLayer: Send + Sync {
+    /// Reads the layer parameters from its line of the params.
+    fn load_param(&mut self, _pd: &ParamDict) -> Result<()> {
+        Ok(())
+    }
+
+    /// Reads the layer weights, in the order they were written to the model.
+    fn load_model(&mut self, _mb: &ModelBin) -> Result<()> {
+        Ok(())
+    }
+
+    /// Computes one output per top blob of the layer from its bottom blobs, in order.
+    fn forward(&self, bottoms: &[&Mat], opt: &crate::option::Option) -> Result<Vec<Mat>>;
+
+    /// Prepares the layer for running once its weights are loaded.
+    fn create_pipeline(&mut self, _opt: &crate::option::Option) -> Result<()> {
+        Ok(())
+    }
+
+    /// Releases what [CustomLayer::create_pipeline] prepared.
+    fn destroy_pipeline(&mut self, _opt: &crate::option::Option) -> Result<()> {
+        Ok(())
+    }
+}
+
+type Factory = dyn Fn() -> Box<dyn CustomLayer> + Send + Sync;
+
+/// Layer type registered on a network, creating a [CustomLayer] for each layer of the type.
+pub(crate) struct CustomLayerType {
+    pub(crate) type_name: String,
+    factory: Box<Factory>,
+}
+
+impl CustomLayerType {
+    pub(crate) fn new<L: CustomLayer + 'static>(
+        type_name: &str,
+        factory: impl Fn() -> L + Send + Sync + 'static,
+    ) -> Result<Box<Self>> {
+        let c_str = CString::new(type_name)
+            .map_err(|_| Error::InvalidArgument(format!("Invalid layer type `{}`", type_name)))?;
+        if unsafe { ncnn_layer_type_to_index(c_str.as_ptr()) } != -1 {
+            return Err(Error::InvalidArgument(format!(
+                "Built-in layer type `{}` cannot be replaced",
+                type_name
+            )));
+        }
+
+        Ok(Box::new(Self {
+            type_name: type_name.to_string(),
+            factory: Box::new(move || Box::new(factory())),
+        }))
+    }
+
+    /// Registers the type on `net`, which must be destroyed before the type is dropped.
+    pub(crate) unsafe fn register(&self, net: ncnn_net_t) {
+        let type_name = CString::new(self.type_name.as_str()).unwrap();
+        ncnn_net_register_custom_layer_by_type(
+            net,
+            type_name.as_ptr(),
+            Some(create_layer),
+            Some(destroy_layer),
+            self as *const Self as *mut c_void,
+        );
+    }
+}
+
+struct LayerState {
+    layer: Box<dyn CustomLayer>,
+    // Allocators of the outputs handed over to ncnn, kept until the layer is destroyed.
+    allocators: Mutex<Vec<Allocator>>,
+}
+
+impl LayerState {
+    fn hand_over(&self, mat: Mat) -> ncnn_mat_t {
+        let (ptr, allocators) = mat.into_raw();
+        let mut kept = self.allocators.lock().unwrap();
+        for alloc in allocators {
+            if !kept.iter().any(|a| a.same(&alloc)) {
+                kept.push(alloc);
+            }
+        }
+        ptr
+    }
+}
+
+// ncnn hands callbacks the C layer struct only, the Rust layers are found by its address.
+static LAYERS: RwLock<BTreeMap<usize, usize>> = RwLock::new(BTreeMap::new());
+
+fn state_for(layer: ncnn_layer_t) -> Option<*mut LayerState> {
+    LAYERS
+        .read()
+        .unwrap()
+        .get(&(layer as usize))
+        .map(|s| *s as *mut LayerState)
+}
+
+fn to_code(result: Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+unsafe extern "C" fn create_layer(userdata: *mut c_void) -> ncnn_layer_t {
+    let layer_type = &*(userdata as *const CustomLayerType);
+    let state = Box::new(LayerState {
+        layer: (layer_type.factory)(),
+        allocators: Mutex::new(Vec::new()),
+    });
+
+    // Layers start as multi blob, not in place and without packing or reduced precision
+    // storage, so that only forward_n is called with plain fp32 data.
+    let ptr = ncnn_layer_create();
+    (*ptr).load_param = Some(layer_load_param);
+    (*ptr).load_model = Some(layer_load_model);
+    (*ptr).create_pipeline = Some(layer_create_pipeline);
+    (*ptr).destroy_pipeline = Some(layer_destroy_pipeline);
+    (*ptr).forward_n = Some(layer_forward_n);
+
+    LAYERS
+        .write()
+        .unwrap()
+        .insert(ptr as usize, Box::into_raw(state) as usize);
+    ptr
+}
+
+unsafe extern "C" fn destroy_layer(layer: ncnn_layer_t, _userdata: *mut c_void) {
+    if let Some(state) = LAYERS.write().unwrap().remove(&(layer as usize)) {
+        drop(Box::from_raw(state as *mut LayerState));
+    }
+    ncnn_layer_destroy(layer);
+}
+
+unsafe extern "C" fn layer_load_param(layer: ncnn_layer_t, pd: ncnn_paramdict_t) -> c_int {
+    match state_for(layer) {
+        Some(state) => to_code((*state).layer.load_param(&ParamDict::borrow_raw(pd))),
+        None => -1,
+    }
+}
+
+unsafe extern "C" fn layer_load_model(layer: ncnn_layer_t, mb: ncnn_modelbin_t) -> c_int {
+    match state_for(layer) {
+        Some(state) => to_code((*state).layer.load_model(&ModelBin::borrow_raw(mb))),
+        None => -1,
+    }
+}
+
+unsafe extern "C" fn layer_create_pipeline(layer: ncnn_layer_t, opt: ncnn_option_t) -> c_int {
+    match state_for(layer) {
+        Some(state) => to_code(
+            (*state)
+                .layer
+                .create_pipeline(&crate::option::Option::borrow_raw(opt)),
+        ),
+        None => -1,
+    }
+}
+
+unsafe extern "C" fn layer_destroy_pipeline(layer: ncnn_layer_t, opt: ncnn_option_t) -> c_int {
+    match state_for(layer) {
+        Some(state) => to_code(
+            (*state)
+                .layer
+                .destroy_pipeline(&crate::option::Option::borrow_raw(opt)),
+        ),
+        None => -1,
+    }
+}
+
+unsafe extern "C" fn layer_forward_n(
+    layer: ncnn_layer_t,
+    bottom_blobs: *const ncnn_mat_t,
+    n: c_int,
+    top_blobs: *mut ncnn_mat_t,
+    n2: c_int,
+    opt: ncnn_option_t,
+) -> c_int {
+    let tops = core::slice::from_raw_parts_mut(top_blobs, n2.max(0) as usize);
+    let outputs = match state_for(layer) {
+        Some(state) => {
+            let bottoms: Vec<_> = core::slice::from_raw_parts(bottom_blobs, n.max(0) as usize)
+                .iter()
+                .map(|&ptr| Mat::borrow_raw(ptr))
+                .collect();
+            let bottoms: Vec<&Mat> = bottoms.iter().map(|mat| &**mat).collect();
+            let opt = crate::option::Option::borrow_raw(opt);
+
+            match (*state).layer.forward(&bottoms, &opt) {
+                Ok(outputs) if outputs.len() == tops.len() => Some((state, outputs)),
+                _ => None,
+            }
+        }
+        None => None,
+    };
+
+    // ncnn takes every top blob back even on failure, so they must all be valid matrices.
+    match outputs {
+        Some((state, outputs)) => {
+            for (top, mat) in tops.iter_mut().zip(outputs) {
+                *top = (*state).hand_over(mat);
+            }
+            0
+        }
+        None => {
+            for top in tops.iter_mut() {
+                *top = ncnn_mat_create();
+            }
+            -1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DataReader, NetBuilder};
+
+    struct Double {
+        factor: f32,
+    }
+
+    impl CustomLayer for Double {
+        fn load_param(&mut self, pd: &ParamDict) -> Result<()> {
+            self.factor = pd.get_float(0, 2.0);
+            Ok(())
+        }
+
+        fn forward(&self, bottoms: &[&Mat], _opt: &crate::option::Option) -> Result<Vec<Mat>> {
+            let bottom = bottoms[0];
+            let mut top = Mat::new_1d(bottom.width(), None)?;
+            for (t, b) in top
+                .as_mut_slice::<f32>()?
+                .iter_mut()
+                .zip(bottom.as_slice::<f32>()?)
+            {
+                *t = b * self.factor;
+            }
+            Ok(vec![top])
+        }
+    }
+
+    #[test]
+    fn forward_custom_layer() {
+        let mut net = NetBuilder::new()
+            .register_custom_layer("Double", || Double { factor: 0.0 })
+            .unwrap()
+            .set_param_str(
+                "7767517\n2 2\nInput data 0 1 data 0=4\nDouble double 1 1 data output 0=3\n",
+            )
+            .unwrap()
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .unwrap();
+
+        let mut input = Mat::new_1d(4, None).unwrap();
+        input.fill(1.5);
+        let mut output = Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!(&[4.5; 4], output.as_slice::<f32>().unwrap());
+    }
+
+    #[test]
+    fn reject_builtin_type() {
+        assert!(NetBuilder::new()
+            .register_custom_layer("ReLU", || Double { factor: 0.0 })
+            .is_err());
+        assert!(NetBuilder::new()
+            .register_custom_layer("Double", || Double { factor: 0.0 })
+            .unwrap()
+            .register_custom_layer("Double", || Double { factor: 0.0 })
+            .is_err());
+    }
+}
@@ -1,18 +1,37 @@
 mod allocator;
+#[cfg(feature = "encryption")]
+pub mod crypt;
 mod datareader;
+mod error;
 mod extractor;
 mod mat;
+#[cfg(feature = "image")]
+mod mat_image;
+#[cfg(feature = "ndarray")]
+mod mat_ndarray;
+mod modelbin;
 mod net;
 mod option;
+mod paramdict;
 mod layer;
+mod layer_custom;
+pub mod param;
 
 pub use allocator::*;
 pub use datareader::*;
+pub use error::*;
 pub use extractor::*;
 pub use mat::*;
+#[cfg(feature = "image")]
+pub use mat_image::*;
+#[cfg(feature = "ndarray")]
+pub use mat_ndarray::*;
+pub use modelbin::*;
 pub use net::*;
 pub use option::*;
+pub use paramdict::*;
 pub use layer::*;
+pub use layer_custom::*;
 
 pub use ncnn_bind as ncnn;
 
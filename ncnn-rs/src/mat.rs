@@ -1,5 +1,7 @@
 use crate::allocator::Allocator;
+use crate::error::{ensure, Error, Result};
 use core::fmt;
+use core::mem::ManuallyDrop;
 use ncnn_bind::*;
 use std::os::raw::c_void;
 
@@ -138,6 +140,37 @@ impl PixelType {
             | PixelType::RgbaToRgb => 4,
         }
     }
+
+    /// Bytes per pixel on the converted side, used when writing pixels out of a matrix.
+    fn target_stride(&self) -> i32 {
+        match self {
+            PixelType::Gray
+            | PixelType::RgbToGray
+            | PixelType::BgrToGray
+            | PixelType::RgbaToGray
+            | PixelType::BgraToGray => 1,
+            PixelType::Bgr
+            | PixelType::Rgb
+            | PixelType::RgbToBgr
+            | PixelType::BgrToRgb
+            | PixelType::GrayToRgb
+            | PixelType::GrayToBgr
+            | PixelType::RgbaToRgb
+            | PixelType::RgbaToBgr
+            | PixelType::BgraToRgb
+            | PixelType::BgraToBgr => 3,
+            PixelType::Bgra
+            | PixelType::Rgba
+            | PixelType::RgbToRgba
+            | PixelType::RgbToBgra
+            | PixelType::BgrToRgba
+            | PixelType::BgrToBgra
+            | PixelType::GrayToRgba
+            | PixelType::GrayToBgra
+            | PixelType::RgbaToBgra
+            | PixelType::BgraToRgba => 4,
+        }
+    }
 }
 
 pub enum BorderType {
@@ -158,13 +191,112 @@ impl BorderType {
     }
 }
 
-fn cast_into_i32(val: u32, name: &str) -> anyhow::Result<i32> {
+pub(crate) fn cast_into_i32(val: u32, name: &str) -> Result<i32> {
     val.try_into()
-        .map_err(|_| anyhow::format_err!("Invalid {} size", name))
+        .map_err(|_| Error::InvalidDimension(format!("Invalid {} size", name)))
+}
+
+/// Checks that a pixel buffer with the given geometry fits in `len` bytes.
+///
+/// The last row only needs to hold its pixels, not the whole stride.
+fn check_pixels_len(
+    len: usize,
+    width: u32,
+    height: u32,
+    stride: u32,
+    pixel_stride: i32,
+) -> Result<()> {
+    let row = width as usize * pixel_stride as usize;
+    ensure!(
+        stride as usize >= row,
+        Error::ShapeMismatch(format!(
+            "Expected stride of at least {}, provided {}",
+            row, stride
+        ))
+    );
+
+    let expected = match height as usize {
+        0 => 0,
+        height => (height - 1) * stride as usize + row,
+    };
+    ensure!(
+        len >= expected,
+        Error::ShapeMismatch(format!(
+            "Expected data length {}, provided {}",
+            expected, len
+        ))
+    );
+
+    Ok(())
 }
 
+/// Pixel bytes with their layout, read by [Mat::from_pixels_with_stride] and
+/// [Mat::from_pixels_resize_with_stride].
+pub struct PixelFrame<'a> {
+    pub data: &'a [u8],
+    /// Layout of the pixels, the source side of a conversion.
+    pub pixel_type: PixelType,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes from the start of a row to the start of the next one.
+    pub stride: u32,
+}
+
+impl<'a> PixelFrame<'a> {
+    /// Frame whose rows are stored back to back.
+    pub fn new(data: &'a [u8], pixel_type: PixelType, width: u32, height: u32) -> Self {
+        let stride = width.saturating_mul(pixel_type.stride() as u32);
+        Self {
+            data,
+            pixel_type,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    /// Sets the bytes between the start of two rows, for padded rows.
+    pub fn with_stride(mut self, stride: u32) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    /// Checks that the data holds the frame and returns its width, height and stride for ncnn.
+    fn checked_geometry(&self) -> Result<(i32, i32, i32)> {
+        check_pixels_len(
+            self.data.len(),
+            self.width,
+            self.height,
+            self.stride,
+            self.pixel_type.stride(),
+        )?;
+        Ok((
+            cast_into_i32(self.width, "width")?,
+            cast_into_i32(self.height, "height")?,
+            cast_into_i32(self.stride, "stride")?,
+        ))
+    }
+}
+
+/// Plain element types which can be viewed through [Mat] slices.
+///
+/// # Safety
+///
+/// Implementors must be plain old data: every bit pattern has to be a valid value.
+pub unsafe trait MatElement: Copy + 'static {}
+
+unsafe impl MatElement for f32 {}
+unsafe impl MatElement for i32 {}
+unsafe impl MatElement for u32 {}
+unsafe impl MatElement for i16 {}
+unsafe impl MatElement for u16 {}
+unsafe impl MatElement for i8 {}
+unsafe impl MatElement for u8 {}
+
 pub struct Mat {
     ptr: ncnn_mat_t,
+    // Allocators the data may belong to, kept alive until the matrix is dropped.
+    allocators: Vec<Allocator>,
 }
 
 // Mat is basically a glorified atomically refcounted matrix.
@@ -176,26 +308,37 @@ impl Mat {
         Self::default()
     }
 
+    /// Checks that the data of a newly created matrix could be allocated.
+    fn check_allocated(self) -> Result<Self> {
+        if self.data().is_null() && self.channel_step() as usize * self.channel_count() > 0 {
+            Err(Error::Allocation)
+        } else {
+            Ok(self)
+        }
+    }
+
     /// Constructs an empty 1D matrix.
-    pub fn new_1d(width: u32, alloc: Option<&Allocator>) -> anyhow::Result<Self> {
+    pub fn new_1d(width: u32, alloc: Option<&Allocator>) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
 
-        Ok(Self {
+        Self {
             ptr: unsafe {
                 ncnn_mat_create_1d(
                     w,
                     alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
                 )
             },
-        })
+            allocators: alloc.into_iter().cloned().collect(),
+        }
+        .check_allocated()
     }
 
     /// Constructs an empty 2D matrix.
-    pub fn new_2d(width: u32, height: u32, alloc: Option<&Allocator>) -> anyhow::Result<Self> {
+    pub fn new_2d(width: u32, height: u32, alloc: Option<&Allocator>) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
         let h = cast_into_i32(height, "height")?;
 
-        Ok(Self {
+        Self {
             ptr: unsafe {
                 ncnn_mat_create_2d(
                     w,
@@ -203,7 +346,9 @@ impl Mat {
                     alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
                 )
             },
-        })
+            allocators: alloc.into_iter().cloned().collect(),
+        }
+        .check_allocated()
     }
 
     /// Constructs an empty 3D matrix.
@@ -212,12 +357,12 @@ impl Mat {
         height: u32,
         channels: u32,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
+    ) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
         let h = cast_into_i32(height, "height")?;
         let c = cast_into_i32(channels, "channels")?;
 
-        Ok(Self {
+        Self {
             ptr: unsafe {
                 ncnn_mat_create_3d(
                     w,
@@ -226,7 +371,9 @@ impl Mat {
                     alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
                 )
             },
-        })
+            allocators: alloc.into_iter().cloned().collect(),
+        }
+        .check_allocated()
     }
 
     /// Constructs an empty 4D matrix.
@@ -236,13 +383,13 @@ impl Mat {
         channels: u32,
         depth: u32,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
+    ) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
         let h = cast_into_i32(height, "height")?;
         let c = cast_into_i32(channels, "channels")?;
         let d = cast_into_i32(depth, "depth")?;
 
-        Ok(Self {
+        Self {
             ptr: unsafe {
                 ncnn_mat_create_4d(
                     w,
@@ -252,7 +399,9 @@ impl Mat {
                     alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
                 )
             },
-        })
+            allocators: alloc.into_iter().cloned().collect(),
+        }
+        .check_allocated()
     }
 
     /// Constructs 1D matrix with a given raw data.
@@ -264,7 +413,7 @@ impl Mat {
         width: u32,
         data: *mut c_void,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
+    ) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
 
         Ok(Self {
@@ -273,6 +422,7 @@ impl Mat {
                 data,
                 alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
             ),
+            allocators: alloc.into_iter().cloned().collect(),
         })
     }
 
@@ -286,7 +436,7 @@ impl Mat {
         height: u32,
         data: *mut c_void,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
+    ) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
         let h = cast_into_i32(height, "height")?;
 
@@ -297,6 +447,7 @@ impl Mat {
                 data,
                 alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
             ),
+            allocators: alloc.into_iter().cloned().collect(),
         })
     }
 
@@ -311,7 +462,7 @@ impl Mat {
         channels: u32,
         data: *mut c_void,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
+    ) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
         let h = cast_into_i32(height, "height")?;
         let c = cast_into_i32(channels, "channels")?;
@@ -324,6 +475,7 @@ impl Mat {
                 data,
                 alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
             ),
+            allocators: alloc.into_iter().cloned().collect(),
         })
     }
 
@@ -339,7 +491,7 @@ impl Mat {
         channels: u32,
         data: *mut c_void,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
+    ) -> Result<Self> {
         let w = cast_into_i32(width, "width")?;
         let h = cast_into_i32(height, "height")?;
         let c = cast_into_i32(channels, "channels")?;
@@ -354,6 +506,7 @@ impl Mat {
                 data,
                 alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
             ),
+            allocators: alloc.into_iter().cloned().collect(),
         })
     }
 
@@ -364,30 +517,28 @@ impl Mat {
         width: u32,
         height: u32,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
-        let width = cast_into_i32(width, "width")?;
-        let height = cast_into_i32(height, "height")?;
+    ) -> Result<Self> {
+        Self::from_pixels_with_stride(&PixelFrame::new(data, pixel_type, width, height), alloc)
+    }
 
-        let len = width * height * pixel_type.stride();
-        anyhow::ensure!(
-            data.len() >= len as _,
-            "Expected data length {}, provided {}",
-            len,
-            data.len()
-        );
+    /// Constructs matrix from a pixel frame whose rows may be padded.
+    pub fn from_pixels_with_stride(frame: &PixelFrame, alloc: Option<&Allocator>) -> Result<Self> {
+        let (width, height, stride) = frame.checked_geometry()?;
 
-        Ok(Self {
+        Self {
             ptr: unsafe {
                 ncnn_mat_from_pixels(
-                    data.as_ptr(),
-                    pixel_type.to_int(),
+                    frame.data.as_ptr(),
+                    frame.pixel_type.to_int(),
                     width,
                     height,
-                    width * pixel_type.stride(),
+                    stride,
                     alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
                 )
             },
-        })
+            allocators: alloc.into_iter().cloned().collect(),
+        }
+        .check_allocated()
     }
 
     /// Constructs matrix from resizing a pixel byte array.
@@ -399,51 +550,176 @@ impl Mat {
         target_width: u32,
         target_height: u32,
         alloc: Option<&Allocator>,
-    ) -> anyhow::Result<Self> {
-        let width = cast_into_i32(width, "width")?;
-        let height = cast_into_i32(height, "height")?;
+    ) -> Result<Self> {
+        Self::from_pixels_resize_with_stride(
+            &PixelFrame::new(data, pixel_type, width, height),
+            target_width,
+            target_height,
+            alloc,
+        )
+    }
+
+    /// Constructs matrix from resizing a pixel frame whose rows may be padded.
+    pub fn from_pixels_resize_with_stride(
+        frame: &PixelFrame,
+        target_width: u32,
+        target_height: u32,
+        alloc: Option<&Allocator>,
+    ) -> Result<Self> {
+        let (width, height, stride) = frame.checked_geometry()?;
         let target_width = cast_into_i32(target_width, "target_width")?;
         let target_height = cast_into_i32(target_height, "target_height")?;
 
-        let len = width * height * pixel_type.stride();
-        anyhow::ensure!(
-            data.len() >= len as _,
-            "Expected data length {}, provided {}",
-            len,
-            data.len()
-        );
-
-        Ok(Self {
+        Self {
             ptr: unsafe {
                 ncnn_mat_from_pixels_resize(
-                    data.as_ptr(),
-                    pixel_type.to_int(),
+                    frame.data.as_ptr(),
+                    frame.pixel_type.to_int(),
                     width,
                     height,
-                    width * pixel_type.stride(),
+                    stride,
                     target_width,
                     target_height,
                     alloc.map(Allocator::ptr).unwrap_or(core::ptr::null_mut()),
                 )
             },
-        })
+            allocators: alloc.into_iter().cloned().collect(),
+        }
+        .check_allocated()
+    }
+
+    /// Checks that the matrix holds unpacked 32 bit values in as many channels as the source
+    /// side of `pixel_type`, which ncnn reads without checking.
+    fn check_pixels_source(&self, pixel_type: &PixelType) -> Result<()> {
+        let channels = pixel_type.stride() as u32;
+        ensure!(
+            self.dimensions() == 3
+                && self.channels() == channels
+                && self.element_size() == 4
+                && self.element_packing() == 1,
+            Error::ShapeMismatch(format!(
+                "Expected a 3D matrix with {} channels of unpacked 32 bit elements, matrix has \
+                 {} dimensions, {} channels, element size {} and packing {}",
+                channels,
+                self.dimensions(),
+                self.channels(),
+                self.element_size(),
+                self.element_packing()
+            ))
+        );
+        Ok(())
+    }
+
+    /// Writes matrix content into a pixel byte array.
+    ///
+    /// The pixel layout written is the converted side of `pixel_type`, e.g. [PixelType::RgbToBgr] writes BGR.
+    /// The matrix must have as many channels as the source side, e.g. 3 for [PixelType::RgbToGray].
+    pub fn to_pixels(&self, data: &mut [u8], pixel_type: PixelType) -> Result<()> {
+        let stride = self
+            .width()
+            .saturating_mul(pixel_type.target_stride() as u32);
+        self.to_pixels_with_stride(data, pixel_type, stride)
+    }
+
+    /// Writes matrix content into a pixel byte array whose rows are `stride` bytes apart.
+    ///
+    /// Padding bytes between rows are left untouched.
+    pub fn to_pixels_with_stride(
+        &self,
+        data: &mut [u8],
+        pixel_type: PixelType,
+        stride: u32,
+    ) -> Result<()> {
+        self.check_pixels_source(&pixel_type)?;
+        check_pixels_len(
+            data.len(),
+            self.width(),
+            self.height(),
+            stride,
+            pixel_type.target_stride(),
+        )?;
+
+        let stride = cast_into_i32(stride, "stride")?;
+
+        unsafe {
+            ncnn_mat_to_pixels(self.ptr, data.as_mut_ptr(), pixel_type.to_int(), stride);
+        }
+
+        Ok(())
+    }
+
+    /// Writes matrix content resized to the target size into a pixel byte array.
+    ///
+    /// The matrix must match the source side of `pixel_type` as with [Mat::to_pixels].
+    pub fn to_pixels_resize(
+        &self,
+        data: &mut [u8],
+        pixel_type: PixelType,
+        target_width: u32,
+        target_height: u32,
+    ) -> Result<()> {
+        let target_stride = target_width.saturating_mul(pixel_type.target_stride() as u32);
+        self.to_pixels_resize_with_stride(
+            data,
+            pixel_type,
+            target_width,
+            target_height,
+            target_stride,
+        )
+    }
+
+    /// Writes matrix content resized to the target size into a pixel byte array whose rows are
+    /// `target_stride` bytes apart.
+    pub fn to_pixels_resize_with_stride(
+        &self,
+        data: &mut [u8],
+        pixel_type: PixelType,
+        target_width: u32,
+        target_height: u32,
+        target_stride: u32,
+    ) -> Result<()> {
+        self.check_pixels_source(&pixel_type)?;
+        check_pixels_len(
+            data.len(),
+            target_width,
+            target_height,
+            target_stride,
+            pixel_type.target_stride(),
+        )?;
+
+        let target_width = cast_into_i32(target_width, "target_width")?;
+        let target_height = cast_into_i32(target_height, "target_height")?;
+        let target_stride = cast_into_i32(target_stride, "target_stride")?;
+
+        unsafe {
+            ncnn_mat_to_pixels_resize(
+                self.ptr,
+                data.as_mut_ptr(),
+                pixel_type.to_int(),
+                target_width,
+                target_height,
+                target_stride,
+            );
+        }
+
+        Ok(())
     }
 
     pub fn subtract_mean_normalize(
         &mut self,
         mean_vals: Option<&[f32]>,
         norm_vals: Option<&[f32]>,
-    ) -> anyhow::Result<()> {
-        fn map_vals(vals: Option<&[f32]>, channels: usize) -> anyhow::Result<*const f32> {
+    ) -> Result<()> {
+        fn map_vals(vals: Option<&[f32]>, channels: usize) -> Result<*const f32> {
             match vals {
                 None => Ok(core::ptr::null_mut()),
                 Some(vals) => {
                     if vals.len() < channels {
-                        Err(anyhow::anyhow!(
+                        Err(Error::ShapeMismatch(format!(
                             "Expected data length {}, provided {}",
                             channels,
                             vals.len()
-                        ))
+                        )))
                     } else {
                         Ok(vals.as_ptr())
                     }
@@ -473,7 +749,7 @@ impl Mat {
         border_type: BorderType,
         value: f32,
         opt: &crate::option::Option,
-    ) -> anyhow::Result<()> {
+    ) -> Result<()> {
         unsafe {
             let src = self.ptr;
             let dst = ncnn_mat_create();
@@ -489,6 +765,7 @@ impl Mat {
 
             ncnn_mat_destroy(src);
         }
+        self.keep_allocators(&opt.allocators());
 
         Ok(())
     }
@@ -505,7 +782,7 @@ impl Mat {
         border_type: BorderType,
         value: f32,
         opt: &crate::option::Option,
-    ) -> anyhow::Result<()> {
+    ) -> Result<()> {
         unsafe {
             let src = self.ptr();
             let dst = ncnn_mat_create();
@@ -536,6 +813,7 @@ impl Mat {
 
             ncnn_mat_destroy(src);
         }
+        self.keep_allocators(&opt.allocators());
 
         Ok(())
     }
@@ -547,7 +825,7 @@ impl Mat {
         left: u32,
         right: u32,
         opt: &crate::option::Option,
-    ) -> anyhow::Result<()> {
+    ) -> Result<()> {
         unsafe {
             let src = self.ptr;
             let dst = ncnn_mat_create();
@@ -564,6 +842,7 @@ impl Mat {
 
             ncnn_mat_destroy(src);
         }
+        self.keep_allocators(&opt.allocators());
 
         Ok(())
     }
@@ -577,7 +856,7 @@ impl Mat {
         front: u32,
         behind: u32,
         opt: &crate::option::Option,
-    ) -> anyhow::Result<()> {
+    ) -> Result<()> {
         unsafe {
             let src = self.ptr;
             let dst = ncnn_mat_create();
@@ -595,6 +874,7 @@ impl Mat {
 
             ncnn_mat_destroy(src);
         }
+        self.keep_allocators(&opt.allocators());
 
         Ok(())
     }
@@ -646,19 +926,239 @@ impl Mat {
         unsafe { ncnn_mat_get_data(self.ptr) }
     }
 
+    /// Number of elements in a single channel, padding excluded.
+    pub(crate) fn channel_len(&self) -> usize {
+        match self.dimensions() {
+            0 => 0,
+            1 => self.width() as usize,
+            2 => self.width() as usize * self.height() as usize,
+            _ => self.width() as usize * self.height() as usize * self.depth() as usize,
+        }
+    }
+
+    /// Number of channels as seen by the slice accessors.
+    pub(crate) fn channel_count(&self) -> usize {
+        match self.dimensions() {
+            0 => 0,
+            1 | 2 => 1,
+            _ => self.channels() as usize,
+        }
+    }
+
+    /// Checks that `T` matches the matrix element layout and returns a typed data pointer, null
+    /// for an empty matrix.
+    pub(crate) fn typed_data<T: MatElement>(&self) -> Result<*mut T> {
+        // Empty matrices have neither element size nor packing.
+        if self.dimensions() == 0 || self.data().is_null() {
+            return Ok(core::ptr::null_mut());
+        }
+
+        ensure!(
+            self.element_packing() == 1,
+            Error::Layout(format!(
+                "Expected element packing 1, matrix has {}",
+                self.element_packing()
+            ))
+        );
+        ensure!(
+            self.element_size() as usize == core::mem::size_of::<T>(),
+            Error::Layout(format!(
+                "Expected element size {}, matrix has {}",
+                core::mem::size_of::<T>(),
+                self.element_size()
+            ))
+        );
+
+        let data = self.data() as *mut T;
+        ensure!(
+            data.align_offset(core::mem::align_of::<T>()) == 0,
+            Error::Layout("Matrix data is not aligned for the requested element type".into())
+        );
+        Ok(data)
+    }
+
+    /// Checks that no other matrix shares the data, so that mutable slices cannot alias.
+    ///
+    /// Data shared through ncnn reference counting, e.g. with a [crate::ParamDict] array or
+    /// an extracted blob, or external data, whose sharing is unknown, is rejected.
+    pub(crate) fn ensure_unique(&self) -> Result<()> {
+        if self.dimensions() == 0 || self.data().is_null() {
+            return Ok(());
+        }
+        ensure!(
+            unsafe { ncnn_bind_mat_get_refcount(self.ptr) } == 1,
+            Error::Layout(
+                "Matrix data is shared with other matrices or not owned by ncnn, mutable access \
+                 requires a unique owner"
+                    .into()
+            )
+        );
+        Ok(())
+    }
+
+    /// Returns a typed pointer to the start of channel `c` and its length.
+    fn channel_data<T: MatElement>(&self, c: u32) -> Result<(*mut T, usize)> {
+        let data = self.typed_data::<T>()?;
+        let channels = self.channel_count();
+        ensure!(
+            (c as usize) < channels,
+            Error::InvalidDimension(format!(
+                "Channel {} out of range, matrix has {}",
+                c, channels
+            ))
+        );
+
+        let offset = c as usize * self.channel_step() as usize;
+        Ok((unsafe { data.add(offset) }, self.channel_len()))
+    }
+
+    /// Returns a typed pointer to the start of row `y` in channel `c` and its length.
+    fn row_data<T: MatElement>(&self, c: u32, y: u32) -> Result<(*mut T, usize)> {
+        let (data, len) = self.channel_data::<T>(c)?;
+        let width = self.width() as usize;
+        let rows = len.checked_div(width).unwrap_or(0);
+        ensure!(
+            (y as usize) < rows,
+            Error::InvalidDimension(format!("Row {} out of range, channel has {}", y, rows))
+        );
+
+        Ok((unsafe { data.add(y as usize * width) }, width))
+    }
+
+    /// Checks that channels are stored back to back and returns the typed data pointer and total
+    /// length.
+    fn contiguous_data<T: MatElement>(&self) -> Result<(*mut T, usize)> {
+        let data = self.typed_data::<T>()?;
+        let channels = self.channel_count();
+        let channel_len = self.channel_len();
+        ensure!(
+            channels <= 1 || self.channel_step() as usize == channel_len,
+            Error::Layout(format!(
+                "Matrix channels are padded (channel step {}, channel size {}), use channel() \
+                 instead",
+                self.channel_step(),
+                channel_len
+            ))
+        );
+
+        Ok((data, channels * channel_len))
+    }
+
+    /// Views the whole matrix as a slice of `T`.
+    ///
+    /// Fails if `T` does not match the element size, if elements are packed or if channels
+    /// are padded to the channel step, in which case [Mat::channel] should be used.
+    pub fn as_slice<T: MatElement>(&self) -> Result<&[T]> {
+        let (data, len) = self.contiguous_data::<T>()?;
+        Ok(unsafe { slice_from_raw(data, len) })
+    }
+
+    /// Mutable version of [Mat::as_slice].
+    ///
+    /// Also fails if the data is shared with another matrix or external, as with
+    /// [Mat::new_external_1d].
+    pub fn as_mut_slice<T: MatElement>(&mut self) -> Result<&mut [T]> {
+        self.ensure_unique()?;
+        let (data, len) = self.contiguous_data::<T>()?;
+        Ok(unsafe { slice_from_raw_mut(data, len) })
+    }
+
+    /// Views channel `c` as a slice of `T`, excluding the padding up to the channel step.
+    ///
+    /// 1D and 2D matrices have a single channel.
+    pub fn channel<T: MatElement>(&self, c: u32) -> Result<&[T]> {
+        let (data, len) = self.channel_data::<T>(c)?;
+        Ok(unsafe { slice_from_raw(data, len) })
+    }
+
+    /// Mutable version of [Mat::channel], failing on shared data like [Mat::as_mut_slice].
+    pub fn channel_mut<T: MatElement>(&mut self, c: u32) -> Result<&mut [T]> {
+        self.ensure_unique()?;
+        let (data, len) = self.channel_data::<T>(c)?;
+        Ok(unsafe { slice_from_raw_mut(data, len) })
+    }
+
+    /// Views row `y` of channel `c` as a slice of `T`.
+    ///
+    /// Rows of a 4D matrix are numbered across depth, i.e. `y` goes up to `height * depth`.
+    pub fn row<T: MatElement>(&self, c: u32, y: u32) -> Result<&[T]> {
+        let (data, len) = self.row_data::<T>(c, y)?;
+        Ok(unsafe { slice_from_raw(data, len) })
+    }
+
+    /// Mutable version of [Mat::row], failing on shared data like [Mat::as_mut_slice].
+    pub fn row_mut<T: MatElement>(&mut self, c: u32, y: u32) -> Result<&mut [T]> {
+        self.ensure_unique()?;
+        let (data, len) = self.row_data::<T>(c, y)?;
+        Ok(unsafe { slice_from_raw_mut(data, len) })
+    }
+
     pub(crate) fn ptr(&self) -> ncnn_mat_t {
         self.ptr
     }
 
+    /// Takes ownership of a matrix created by ncnn.
+    pub(crate) unsafe fn from_raw(ptr: ncnn_mat_t) -> Self {
+        Self {
+            ptr,
+            allocators: Vec::new(),
+        }
+    }
+
+    /// Wraps a matrix owned by ncnn, which must outlive the wrapper and never be dropped by it.
+    pub(crate) unsafe fn borrow_raw(ptr: ncnn_mat_t) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self {
+            ptr,
+            allocators: Vec::new(),
+        })
+    }
+
+    /// Hands the matrix over to ncnn, returning the allocators its data may belong to.
+    pub(crate) fn into_raw(self) -> (ncnn_mat_t, Vec<Allocator>) {
+        let mut mat = ManuallyDrop::new(self);
+        (mat.ptr, std::mem::take(&mut mat.allocators))
+    }
+
+    /// Allocators the data may belong to.
+    pub(crate) fn allocators(&self) -> &[Allocator] {
+        &self.allocators
+    }
+
+    /// Keeps `allocators` alive as long as the matrix, for data ncnn allocated with them.
+    pub(crate) fn keep_allocators(&mut self, allocators: &[Allocator]) {
+        for alloc in allocators {
+            if !self.allocators.iter().any(|a| a.same(alloc)) {
+                self.allocators.push(alloc.clone());
+            }
+        }
+    }
+
     pub(crate) fn mut_ptr(&mut self) -> *mut ncnn_mat_t {
         &mut self.ptr
     }
 }
 
+unsafe fn slice_from_raw<'a, T>(data: *const T, len: usize) -> &'a [T] {
+    if len == 0 || data.is_null() {
+        &[]
+    } else {
+        core::slice::from_raw_parts(data, len)
+    }
+}
+
+unsafe fn slice_from_raw_mut<'a, T>(data: *mut T, len: usize) -> &'a mut [T] {
+    if len == 0 || data.is_null() {
+        &mut []
+    } else {
+        core::slice::from_raw_parts_mut(data, len)
+    }
+}
+
 impl Default for Mat {
     fn default() -> Self {
         Self {
             ptr: unsafe { ncnn_mat_create() },
+            allocators: Vec::new(),
         }
     }
 }
@@ -696,7 +1196,8 @@ pub fn copy_make_border(
     border_type: BorderType,
     value: f32,
     opt: &crate::option::Option,
-) -> anyhow::Result<()> {
+) -> Result<()> {
+    dst.keep_allocators(&opt.allocators());
     unsafe {
         let src = src.ptr();
         let dst = dst.ptr();
@@ -726,7 +1227,8 @@ pub fn copy_make_border_3d(
     border_type: BorderType,
     value: f32,
     opt: &crate::option::Option,
-) -> anyhow::Result<()> {
+) -> Result<()> {
+    dst.keep_allocators(&opt.allocators());
     unsafe {
         let src = src.ptr();
         let dst = dst.ptr();
@@ -765,7 +1267,8 @@ pub fn copy_cut_border(
     left: u32,
     right: u32,
     opt: &crate::option::Option,
-) -> anyhow::Result<()> {
+) -> Result<()> {
+    dst.keep_allocators(&opt.allocators());
     unsafe {
         let src = src.ptr();
         let dst = dst.ptr();
@@ -791,7 +1294,8 @@ pub fn copy_cut_border_3d(
     front: u32,
     behind: u32,
     opt: &crate::option::Option,
-) -> anyhow::Result<()> {
+) -> Result<()> {
+    dst.keep_allocators(&opt.allocators());
     unsafe {
         let src = src.ptr();
         let dst = dst.ptr();
@@ -821,6 +1325,58 @@ mod tests {
         assert_eq!(3, m.channels());
     }
 
+    #[test]
+    fn typed_access() {
+        let mut m = Mat::new_3d(5, 3, 2, None).unwrap();
+        m.fill(1.0);
+        m.row_mut::<f32>(1, 2).unwrap()[4] = 2.0;
+
+        assert_eq!(15, m.channel::<f32>(0).unwrap().len());
+        assert!(m.channel::<f32>(0).unwrap().iter().all(|v| *v == 1.0));
+        assert_eq!(2.0, m.channel::<f32>(1).unwrap()[14]);
+        assert_eq!(5, m.row::<f32>(0, 0).unwrap().len());
+
+        assert!(m.channel::<f32>(2).is_err());
+        assert!(m.row::<f32>(0, 3).is_err());
+        assert!(m.channel::<u8>(0).is_err());
+        // 15 floats per channel are padded to a 16 byte aligned channel step.
+        assert!(m.as_slice::<f32>().is_err());
+    }
+
+    #[test]
+    fn contiguous_slice() {
+        let mut m = Mat::new_2d(4, 3, None).unwrap();
+        m.as_mut_slice::<f32>()
+            .unwrap()
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = i as f32);
+
+        assert_eq!(12, m.as_slice::<f32>().unwrap().len());
+        assert_eq!(&[8.0, 9.0, 10.0, 11.0], m.row::<f32>(0, 2).unwrap());
+        assert!(Mat::new().as_slice::<f32>().unwrap().is_empty());
+        assert!(Mat::new().as_mut_slice::<f32>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn shared_data_is_read_only() {
+        let mut m = Mat::new_1d(4, None).unwrap();
+        m.fill(1.0);
+        let mut pd = crate::ParamDict::new();
+        pd.set_array(0, &m).unwrap();
+        assert!(m.as_mut_slice::<f32>().is_err());
+        assert!(m.row_mut::<f32>(0, 0).is_err());
+        assert_eq!(&[1.0; 4], m.as_slice::<f32>().unwrap());
+        drop(pd);
+        assert!(m.as_mut_slice::<f32>().is_ok());
+
+        let mut data = [0.0f32; 4];
+        let mut external =
+            unsafe { Mat::new_external_1d(4, data.as_mut_ptr() as *mut c_void, None) }.unwrap();
+        assert!(external.channel_mut::<f32>(0).is_err());
+        assert_eq!(&[0.0; 4], external.channel::<f32>(0).unwrap());
+    }
+
     #[test]
     fn stride() {
         assert_eq!(PixelType::Bgr.stride(), 3);
@@ -854,4 +1410,63 @@ mod tests {
         assert_eq!(PixelType::BgraToGray.stride(), 4);
         assert_eq!(PixelType::BgraToRgba.stride(), 4);
     }
+
+    #[test]
+    fn pixels_len() {
+        assert!(check_pixels_len(12, 2, 2, 6, 3).is_ok());
+        // The last row does not need the stride padding.
+        assert!(check_pixels_len(14, 2, 2, 8, 3).is_ok());
+        assert!(check_pixels_len(13, 2, 2, 8, 3).is_err());
+        assert!(check_pixels_len(100, 2, 2, 5, 3).is_err());
+        assert!(check_pixels_len(0, 2, 0, 6, 3).is_ok());
+    }
+
+    #[test]
+    fn padded_pixels_round_trip() {
+        // 2x2 gray image with rows padded to 4 bytes.
+        let data = [10u8, 20, 0, 0, 30, 40, 0, 0];
+        let frame = PixelFrame::new(&data, PixelType::Gray, 2, 2).with_stride(4);
+        let m = Mat::from_pixels_with_stride(&frame, None).unwrap();
+        assert_eq!(&[10.0, 20.0, 30.0, 40.0], m.as_slice::<f32>().unwrap());
+
+        let mut out = [0xffu8; 8];
+        m.to_pixels_with_stride(&mut out, PixelType::Gray, 4)
+            .unwrap();
+        assert_eq!([10, 20, 0xff, 0xff, 30, 40, 0xff, 0xff], out);
+    }
+
+    #[test]
+    fn pixels_source_mismatch() {
+        let gray = Mat::new_3d(2, 2, 1, None).unwrap();
+        let mut out = [0u8; 12];
+        assert!(gray.to_pixels(&mut out, PixelType::Rgb).is_err());
+        assert!(gray
+            .to_pixels_resize(&mut out, PixelType::RgbToGray, 2, 2)
+            .is_err());
+        assert!(gray.to_pixels(&mut out, PixelType::GrayToRgb).is_ok());
+        assert!(gray
+            .to_pixels_resize_with_stride(&mut out, PixelType::Rgb, 2, 2, 6)
+            .is_err());
+
+        let flat = Mat::new_2d(2, 2, None).unwrap();
+        assert!(flat.to_pixels(&mut out, PixelType::Gray).is_err());
+    }
+
+    #[test]
+    fn target_stride() {
+        assert_eq!(PixelType::Rgb.target_stride(), 3);
+        assert_eq!(PixelType::Gray.target_stride(), 1);
+        assert_eq!(PixelType::RgbToGray.target_stride(), 1);
+        assert_eq!(PixelType::GrayToBgra.target_stride(), 4);
+        assert_eq!(PixelType::RgbaToBgr.target_stride(), 3);
+    }
+
+    #[test]
+    fn outlives_allocator() {
+        let alloc = crate::Allocator::new();
+        let mut m = Mat::new_2d(8, 8, Some(&alloc)).unwrap();
+        drop(alloc);
+        m.fill(2.0);
+        assert_eq!(&[2.0; 64], m.as_slice::<f32>().unwrap());
+    }
 }
@@ -0,0 +1,120 @@
+use crate::allocator::Allocator;
+use crate::error::{Error, Result};
+use crate::mat::{Mat, PixelType};
+use core::ops::Deref;
+use image::{DynamicImage, ImageBuffer, Luma, Pixel, Rgb, Rgba};
+
+/// 8-bit image pixels with a matching ncnn pixel layout.
+pub trait MatPixel: Pixel<Subpixel = u8> {
+    /// Layout of the pixel as understood by ncnn.
+    fn pixel_type() -> PixelType;
+}
+
+impl MatPixel for Rgb<u8> {
+    fn pixel_type() -> PixelType {
+        PixelType::Rgb
+    }
+}
+
+impl MatPixel for Rgba<u8> {
+    fn pixel_type() -> PixelType {
+        PixelType::Rgba
+    }
+}
+
+impl MatPixel for Luma<u8> {
+    fn pixel_type() -> PixelType {
+        PixelType::Gray
+    }
+}
+
+impl Mat {
+    /// Constructs matrix from an image buffer, keeping its channel order.
+    ///
+    /// Use [Mat::from_pixels] on the raw buffer for channel conversions, e.g. [PixelType::RgbToBgr].
+    pub fn from_image<P, C>(image: &ImageBuffer<P, C>, alloc: Option<&Allocator>) -> Result<Self>
+    where
+        P: MatPixel,
+        C: Deref<Target = [u8]>,
+    {
+        let (width, height) = image.dimensions();
+        Mat::from_pixels(image.as_raw(), P::pixel_type(), width, height, alloc)
+    }
+
+    /// Constructs matrix from resizing an image buffer, keeping its channel order.
+    pub fn from_image_resize<P, C>(
+        image: &ImageBuffer<P, C>,
+        target_width: u32,
+        target_height: u32,
+        alloc: Option<&Allocator>,
+    ) -> Result<Self>
+    where
+        P: MatPixel,
+        C: Deref<Target = [u8]>,
+    {
+        let (width, height) = image.dimensions();
+        Mat::from_pixels_resize(
+            image.as_raw(),
+            P::pixel_type(),
+            width,
+            height,
+            target_width,
+            target_height,
+            alloc,
+        )
+    }
+
+    /// Constructs matrix from a dynamic image.
+    ///
+    /// Gray, RGB and RGBA 8-bit images are used as they are, any other format is converted to RGB first.
+    pub fn from_dynamic_image(image: &DynamicImage, alloc: Option<&Allocator>) -> Result<Self> {
+        match image {
+            DynamicImage::ImageLuma8(image) => Mat::from_image(image, alloc),
+            DynamicImage::ImageRgb8(image) => Mat::from_image(image, alloc),
+            DynamicImage::ImageRgba8(image) => Mat::from_image(image, alloc),
+            image => Mat::from_image(&image.to_rgb8(), alloc),
+        }
+    }
+
+    /// Converts matrix content into an image buffer, saturating values to 8 bits.
+    ///
+    /// The matrix must have as many channels as the pixel type `P`.
+    pub fn to_image<P: MatPixel>(&self) -> Result<ImageBuffer<P, Vec<u8>>> {
+        let (width, height) = (self.width(), self.height());
+        let mut data = vec![0u8; width as usize * height as usize * P::CHANNEL_COUNT as usize];
+        self.to_pixels(&mut data, P::pixel_type())?;
+
+        ImageBuffer::from_raw(width, height, data)
+            .ok_or_else(|| Error::ShapeMismatch("Invalid image buffer size".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, RgbImage};
+
+    #[test]
+    fn rgb_round_trip() {
+        let image = RgbImage::from_fn(8, 4, |x, y| Rgb([x as u8, y as u8, 200]));
+        let mat = Mat::from_dynamic_image(&DynamicImage::ImageRgb8(image.clone()), None).unwrap();
+        assert_eq!(3, mat.channels());
+        assert_eq!(8, mat.width());
+        assert_eq!(4, mat.height());
+
+        assert_eq!(image, mat.to_image::<Rgb<u8>>().unwrap());
+        assert!(mat.to_image::<Luma<u8>>().is_err());
+    }
+
+    #[test]
+    fn gray_resize() {
+        let image = GrayImage::from_pixel(8, 8, Luma([42]));
+        let mat = Mat::from_image_resize(&image, 4, 2, None).unwrap();
+        assert_eq!(1, mat.channels());
+        assert_eq!(4, mat.width());
+        assert_eq!(2, mat.height());
+
+        let out = mat.to_image::<Luma<u8>>().unwrap();
+        assert!(out.pixels().all(|p| p.0[0] == 42));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::error::{ensure, Error, Result};
+use crate::mat::{Mat, MatElement};
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+use ndarray::{
+    ArrayBase, ArrayViewD, ArrayViewMut, ArrayViewMutD, Axis, Data, Dimension, IxDyn, ShapeBuilder,
+};
+use std::os::raw::c_void;
+
+/// Matrix which may borrow the memory of an ndarray array for the lifetime `'a`.
+///
+/// Dereferences to [Mat], so it can be passed anywhere a matrix is expected.
+pub struct MatView<'a> {
+    mat: Mat,
+    _phantom: PhantomData<&'a mut ()>,
+}
+
+impl<'a> Deref for MatView<'a> {
+    type Target = Mat;
+
+    fn deref(&self) -> &Mat {
+        &self.mat
+    }
+}
+
+impl<'a> DerefMut for MatView<'a> {
+    fn deref_mut(&mut self) -> &mut Mat {
+        &mut self.mat
+    }
+}
+
+fn check_dims(ndim: usize) -> Result<()> {
+    ensure!(
+        (1..=4).contains(&ndim),
+        Error::InvalidDimension(format!(
+            "Expected an array with 1 to 4 dimensions, provided {}",
+            ndim
+        ))
+    );
+    Ok(())
+}
+
+fn dim_into_u32(val: usize) -> Result<u32> {
+    val.try_into()
+        .map_err(|_| Error::InvalidDimension(format!("Invalid dimension size {}", val)))
+}
+
+impl Mat {
+    /// Shape of the matrix in ndarray order: `[w]`, `[h, w]`, `[c, h, w]` or `[c, d, h, w]`.
+    fn array_shape(&self) -> (Vec<usize>, Vec<usize>) {
+        let w = self.width() as usize;
+        let h = self.height() as usize;
+        let d = self.depth() as usize;
+        let c = self.channels() as usize;
+        let cstep = self.channel_step() as usize;
+
+        match self.dimensions() {
+            0 => (vec![0], vec![1]),
+            1 => (vec![w], vec![1]),
+            2 => (vec![h, w], vec![w, 1]),
+            3 => (vec![c, h, w], vec![cstep, w, 1]),
+            _ => (vec![c, d, h, w], vec![cstep, h * w, w, 1]),
+        }
+    }
+
+    /// Views the matrix as an ndarray array, following the channel step between channels.
+    ///
+    /// The shape is `[w]`, `[h, w]`, `[c, h, w]` or `[c, d, h, w]` depending on [Mat::dimensions].
+    pub fn as_array<T: MatElement>(&self) -> Result<ArrayViewD<'_, T>> {
+        let data = self.typed_data::<T>()?;
+        let data = NonNull::new(data).unwrap_or(NonNull::dangling());
+        let (shape, strides) = self.array_shape();
+
+        Ok(unsafe {
+            ArrayViewD::from_shape_ptr(IxDyn(&shape).strides(IxDyn(&strides)), data.as_ptr())
+        })
+    }
+
+    /// Mutable version of [Mat::as_array], failing on shared data like [Mat::as_mut_slice].
+    pub fn as_array_mut<T: MatElement>(&mut self) -> Result<ArrayViewMutD<'_, T>> {
+        self.ensure_unique()?;
+        let data = self.typed_data::<T>()?;
+        let data = NonNull::new(data).unwrap_or(NonNull::dangling());
+        let (shape, strides) = self.array_shape();
+
+        Ok(unsafe {
+            ArrayViewMutD::from_shape_ptr(IxDyn(&shape).strides(IxDyn(&strides)), data.as_ptr())
+        })
+    }
+
+    /// Constructs a matrix by copying an array of 1 to 4 dimensions.
+    ///
+    /// Arrays are interpreted as `[w]`, `[h, w]`, `[c, h, w]` or `[c, d, h, w]`.
+    pub fn from_array<S, D>(array: &ArrayBase<S, D>) -> Result<Self>
+    where
+        S: Data<Elem = f32>,
+        D: Dimension,
+    {
+        let array = array.view().into_dyn();
+        check_dims(array.ndim())?;
+
+        let shape = array
+            .shape()
+            .iter()
+            .map(|v| dim_into_u32(*v))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut mat = match shape.as_slice() {
+            [w] => Mat::new_1d(*w, None)?,
+            [h, w] => Mat::new_2d(*w, *h, None)?,
+            [c, h, w] => Mat::new_3d(*w, *h, *c, None)?,
+            [c, d, h, w] => Mat::new_4d(*w, *h, *c, *d, None)?,
+            _ => unreachable!(),
+        };
+
+        if array.ndim() <= 2 {
+            mat.as_mut_slice::<f32>()?
+                .iter_mut()
+                .zip(array.iter())
+                .for_each(|(dst, src)| *dst = *src);
+        } else {
+            for (c, channel) in array.axis_iter(Axis(0)).enumerate() {
+                mat.channel_mut::<f32>(c as u32)?
+                    .iter_mut()
+                    .zip(channel.iter())
+                    .for_each(|(dst, src)| *dst = *src);
+            }
+        }
+
+        Ok(mat)
+    }
+
+    /// Constructs a matrix from a mutable array view, sharing its memory when possible.
+    ///
+    /// The data is used in place when the view is in standard layout and its channels need no
+    /// padding to the channel step, otherwise it is copied like [Mat::from_array]. Shared data
+    /// is external to ncnn, so it can be modified through ncnn functions such as [Mat::fill]
+    /// but not through mutable slices.
+    pub fn from_array_view<'a, D: Dimension>(
+        mut view: ArrayViewMut<'a, f32, D>,
+    ) -> Result<MatView<'a>> {
+        check_dims(view.ndim())?;
+
+        let shape = view
+            .shape()
+            .iter()
+            .map(|v| dim_into_u32(*v))
+            .collect::<Result<Vec<_>>>()?;
+
+        // ncnn aligns every channel to 16 bytes.
+        let channel_bytes = shape
+            .iter()
+            .skip(1)
+            .try_fold(4usize, |bytes, &v| bytes.checked_mul(v as usize))
+            .ok_or_else(|| {
+                Error::InvalidDimension(format!("Channel size of {:?} overflows", shape))
+            })?;
+        let needs_padding = view.ndim() > 2 && shape[0] > 1 && !channel_bytes.is_multiple_of(16);
+
+        if !view.is_standard_layout() || needs_padding || view.is_empty() {
+            return Ok(MatView {
+                mat: Mat::from_array(&view)?,
+                _phantom: PhantomData,
+            });
+        }
+
+        let data = view.as_mut_ptr() as *mut c_void;
+        let mat = unsafe {
+            match shape.as_slice() {
+                [w] => Mat::new_external_1d(*w, data, None)?,
+                [h, w] => Mat::new_external_2d(*w, *h, data, None)?,
+                [c, h, w] => Mat::new_external_3d(*w, *h, *c, data, None)?,
+                [c, d, h, w] => Mat::new_external_4d(*w, *h, *d, *c, data, None)?,
+                _ => unreachable!(),
+            }
+        };
+
+        Ok(MatView {
+            mat,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{s, Array3};
+
+    #[test]
+    fn view_respects_channel_step() {
+        let mut m = Mat::new_3d(5, 3, 2, None).unwrap();
+        m.fill(1.0);
+        m.row_mut::<f32>(1, 2).unwrap()[4] = 2.0;
+
+        let a = m.as_array::<f32>().unwrap();
+        assert_eq!(&[2, 3, 5], a.shape());
+        assert_eq!(2.0, a[[1, 2, 4]]);
+        assert_eq!(29.0 + 2.0, a.sum());
+    }
+
+    #[test]
+    fn array_round_trip() {
+        let a = Array3::from_shape_fn((2, 3, 5), |(c, y, x)| (c * 100 + y * 10 + x) as f32);
+        let m = Mat::from_array(&a).unwrap();
+        assert_eq!(3, m.dimensions());
+        assert_eq!(a.view().into_dyn(), m.as_array::<f32>().unwrap());
+
+        let m = Mat::from_array(&a.slice(s![.., .., 1..3])).unwrap();
+        assert_eq!(2, m.width());
+        assert_eq!(
+            a.slice(s![.., .., 1..3]).into_dyn(),
+            m.as_array::<f32>().unwrap()
+        );
+    }
+
+    #[test]
+    fn view_shares_memory() {
+        let mut a = Array3::<f32>::zeros((2, 4, 4));
+        {
+            let mut m = Mat::from_array_view(a.view_mut()).unwrap();
+            m.fill(3.0);
+        }
+        assert!(a.iter().all(|v| *v == 3.0));
+    }
+}
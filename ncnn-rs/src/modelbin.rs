@@ -0,0 +1,233 @@
+use crate::datareader::DataReader;
+use crate::error::{Error, Result};
+use crate::mat::{cast_into_i32, Mat};
+use core::mem::ManuallyDrop;
+use ncnn_bind::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::marker::PhantomData;
+use std::os::raw::c_int;
+use std::sync::{Mutex, RwLock};
+
+/// Storage of weights read by a [ModelBin].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightType {
+    /// Read from a flag preceding the data, as written by ncnn's converters: float32, float16
+    /// or int8 quantized.
+    Auto,
+    /// Raw float32 data.
+    Float32,
+}
+
+impl WeightType {
+    fn to_int(self) -> c_int {
+        match self {
+            WeightType::Auto => 0,
+            WeightType::Float32 => 1,
+        }
+    }
+}
+
+/// Source of layer weights, see [crate::Layer::load_model].
+///
+/// Each load consumes the next weights of the source.
+pub struct ModelBin<'a> {
+    ptr: ncnn_modelbin_t,
+    // Source of ModelBin::from_mats, the weights being served by the callbacks below.
+    weights: Option<(DataReader, Box<Weights>)>,
+    _phantom: PhantomData<&'a DataReader>,
+}
+
+unsafe impl Send for ModelBin<'_> {}
+
+struct Weights {
+    mats: Mutex<VecDeque<Mat>>,
+}
+
+// ncnn hands callbacks the C modelbin struct only, weights are found by its address.
+static WEIGHTS: RwLock<BTreeMap<usize, usize>> = RwLock::new(BTreeMap::new());
+
+/// Returns a copy of the next weights shaped by `reshape`, or an empty matrix if there are none.
+unsafe fn next_weights(
+    mb: ncnn_modelbin_t,
+    reshape: impl FnOnce(ncnn_mat_t) -> ncnn_mat_t,
+) -> ncnn_mat_t {
+    let weights = WEIGHTS.read().unwrap().get(&(mb as usize)).copied();
+    let mat = weights.and_then(|w| (*(w as *const Weights)).mats.lock().unwrap().pop_front());
+    match mat {
+        Some(mat) => {
+            // Copied so that layers keep no reference to memory or allocators of the Rust side.
+            let copy = ncnn_mat_clone(mat.ptr(), core::ptr::null_mut());
+            let shaped = reshape(copy);
+            ncnn_mat_destroy(copy);
+            shaped
+        }
+        None => ncnn_mat_create(),
+    }
+}
+
+unsafe extern "C" fn weights_load_1d(mb: ncnn_modelbin_t, w: c_int, _type: c_int) -> ncnn_mat_t {
+    next_weights(mb, |m| ncnn_mat_reshape_1d(m, w, core::ptr::null_mut()))
+}
+
+unsafe extern "C" fn weights_load_2d(
+    mb: ncnn_modelbin_t,
+    w: c_int,
+    h: c_int,
+    _type: c_int,
+) -> ncnn_mat_t {
+    next_weights(mb, |m| ncnn_mat_reshape_2d(m, w, h, core::ptr::null_mut()))
+}
+
+unsafe extern "C" fn weights_load_3d(
+    mb: ncnn_modelbin_t,
+    w: c_int,
+    h: c_int,
+    c: c_int,
+    _type: c_int,
+) -> ncnn_mat_t {
+    next_weights(mb, |m| {
+        ncnn_mat_reshape_3d(m, w, h, c, core::ptr::null_mut())
+    })
+}
+
+impl<'a> ModelBin<'a> {
+    /// Reads weights in ncnn's `.bin` layout from `datareader`.
+    pub fn from_datareader(datareader: &'a DataReader) -> Self {
+        Self {
+            ptr: unsafe { ncnn_modelbin_create_from_datareader(datareader.ptr()) },
+            weights: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Serves `weights` in order, each reshaped to the requested dimensions.
+    pub fn from_mats(weights: impl IntoIterator<Item = Mat>) -> ModelBin<'static> {
+        let datareader = DataReader::empty();
+        let weights = Box::new(Weights {
+            mats: Mutex::new(weights.into_iter().collect()),
+        });
+
+        let ptr = unsafe {
+            let ptr = ncnn_modelbin_create_from_datareader(datareader.ptr());
+            (*ptr).load_1d = Some(weights_load_1d);
+            (*ptr).load_2d = Some(weights_load_2d);
+            (*ptr).load_3d = Some(weights_load_3d);
+            ptr
+        };
+
+        WEIGHTS
+            .write()
+            .unwrap()
+            .insert(ptr as usize, &*weights as *const Weights as usize);
+
+        ModelBin {
+            ptr,
+            weights: Some((datareader, weights)),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn check_loaded(mat: Mat) -> Result<Mat> {
+        if mat.dimensions() == 0 {
+            Err(Error::ModelLoad {
+                path: None,
+                offset: None,
+            })
+        } else {
+            Ok(mat)
+        }
+    }
+
+    /// Loads the next `w` weights.
+    pub fn load_1d(&self, w: u32, weight_type: WeightType) -> Result<Mat> {
+        let w = cast_into_i32(w, "width")?;
+        let load = unsafe { (*self.ptr).load_1d }.ok_or(Error::ModelLoad {
+            path: None,
+            offset: None,
+        })?;
+        let mat = unsafe { Mat::from_raw(load(self.ptr, w, weight_type.to_int())) };
+        Self::check_loaded(mat)
+    }
+
+    /// Loads the next `w * h` weights as a 2D matrix.
+    pub fn load_2d(&self, w: u32, h: u32, weight_type: WeightType) -> Result<Mat> {
+        let w = cast_into_i32(w, "width")?;
+        let h = cast_into_i32(h, "height")?;
+        let load = unsafe { (*self.ptr).load_2d }.ok_or(Error::ModelLoad {
+            path: None,
+            offset: None,
+        })?;
+        let mat = unsafe { Mat::from_raw(load(self.ptr, w, h, weight_type.to_int())) };
+        Self::check_loaded(mat)
+    }
+
+    /// Loads the next `w * h * c` weights as a 3D matrix.
+    pub fn load_3d(&self, w: u32, h: u32, c: u32, weight_type: WeightType) -> Result<Mat> {
+        let w = cast_into_i32(w, "width")?;
+        let h = cast_into_i32(h, "height")?;
+        let c = cast_into_i32(c, "channels")?;
+        let load = unsafe { (*self.ptr).load_3d }.ok_or(Error::ModelLoad {
+            path: None,
+            offset: None,
+        })?;
+        let mat = unsafe { Mat::from_raw(load(self.ptr, w, h, c, weight_type.to_int())) };
+        Self::check_loaded(mat)
+    }
+
+    /// Wraps a modelbin owned by ncnn, which must outlive the wrapper and never be dropped by it.
+    pub(crate) unsafe fn borrow_raw(ptr: ncnn_modelbin_t) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self {
+            ptr,
+            weights: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    pub(crate) fn ptr(&self) -> ncnn_modelbin_t {
+        self.ptr
+    }
+}
+
+impl Drop for ModelBin<'_> {
+    fn drop(&mut self) {
+        if self.weights.is_some() {
+            WEIGHTS.write().unwrap().remove(&(self.ptr as usize));
+        }
+        unsafe {
+            ncnn_modelbin_destroy(self.ptr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_from_mats() {
+        let mut weights = Mat::new_1d(6, None).unwrap();
+        weights
+            .as_mut_slice::<f32>()
+            .unwrap()
+            .copy_from_slice(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let mut bias = Mat::new_1d(2, None).unwrap();
+        bias.fill(0.5);
+
+        let mb = ModelBin::from_mats([weights, bias]);
+        let weights = mb.load_2d(3, 2, WeightType::Auto).unwrap();
+        assert_eq!(2, weights.height());
+        assert_eq!(&[4.0, 5.0, 6.0], weights.row::<f32>(0, 1).unwrap());
+        assert!(mb.load_1d(3, WeightType::Auto).is_err());
+        assert!(mb.load_1d(1, WeightType::Auto).is_err());
+    }
+
+    #[test]
+    fn load_from_datareader() {
+        let data: Vec<u8> = [1.0f32, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let dr = DataReader::from_reader(std::io::Cursor::new(data));
+        let mb = ModelBin::from_datareader(&dr);
+        let mat = mb.load_1d(2, WeightType::Float32).unwrap();
+        assert_eq!(&[1.0, 2.0], mat.as_slice::<f32>().unwrap());
+        assert!(mb.load_1d(2, WeightType::Float32).is_err());
+    }
+}
@@ -1,25 +1,49 @@
+use crate::allocator::Allocator;
 use crate::datareader::DataReader;
+use crate::error::{ensure, Error, Result};
+use crate::layer_custom::{CustomLayer, CustomLayerType};
+use crate::param::{self, Graph};
 use crate::Extractor;
+use memmap2::Mmap;
 use ncnn_bind::*;
-use std::ffi::{CString, OsStr};
+use std::ffi::{CStr, CString, OsStr};
+use std::fs::File;
+use std::marker::PhantomData;
 #[cfg(target_family = "unix")]
 use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 
-enum LoadMethod {
+enum LoadMethod<'a> {
     None,
     Path { path: CString },
+    ParamMemory { data: CString },
+    ParamBinPath { path: CString },
+    ParamBinMemory { data: Vec<u8> },
+    ParamBinDataReader { datareader: DataReader },
+    ModelMemory { data: &'a [u8] },
+    ModelMemoryInPlace { data: &'a [u8] },
+    ModelMmap { map: Mmap, path: PathBuf },
     DataReader { datareader: DataReader },
 }
 
-pub struct NetBuilder {
+/// Builds a [Net] from params and model weights.
+///
+/// The lifetime `'a` is the one of model weights set with [NetBuilder::set_model_memory], which
+/// stay borrowed until [NetBuilder::build], or with [NetBuilder::set_model_memory_in_place], which
+/// ncnn keeps referencing for the whole life of the built [Net].
+pub struct NetBuilder<'a> {
     ptr: Option<ncnn_net_t>,
-    param: LoadMethod,
-    model: LoadMethod,
+    param: LoadMethod<'a>,
+    model: LoadMethod<'a>,
+    allocators: Vec<Allocator>,
+    // Boxed as ncnn keeps their address.
+    #[allow(clippy::vec_box)]
+    custom_layers: Vec<Box<CustomLayerType>>,
 }
 
-unsafe impl Send for NetBuilder {}
+unsafe impl Send for NetBuilder<'_> {}
 
-impl NetBuilder {
+impl<'a> NetBuilder<'a> {
     #[cfg(target_family = "unix")]
     fn os_str_to_cstr(src: &OsStr) -> Option<CString> {
         CString::new(src.as_bytes()).ok()
@@ -30,31 +54,199 @@ impl NetBuilder {
         CString::new(src.to_string_lossy().as_bytes()).ok()
     }
 
+    #[cfg(target_family = "unix")]
+    fn cstr_to_path(src: &CStr) -> Option<PathBuf> {
+        Some(PathBuf::from(OsStr::from_bytes(src.to_bytes())))
+    }
+
+    #[cfg(not(target_family = "unix"))]
+    fn cstr_to_path(src: &CStr) -> Option<PathBuf> {
+        src.to_str().ok().map(PathBuf::from)
+    }
+
+    /// Parses the params again on the Rust side for introspection, when their source allows it.
+    fn parse_graph(&self) -> Option<Graph> {
+        match &self.param {
+            LoadMethod::Path { path } => Graph::from_path(Self::cstr_to_path(path)?).ok(),
+            LoadMethod::ParamMemory { data } => Graph::parse(data.to_str().ok()?).ok(),
+            LoadMethod::ParamBinPath { path } => {
+                Graph::from_bin_path(Self::cstr_to_path(path)?).ok()
+            }
+            LoadMethod::ParamBinMemory { data } => {
+                Graph::from_bin_prefix(data, &self.custom_type_names())
+                    .ok()
+                    .map(|(graph, _)| graph)
+            }
+            _ => None,
+        }
+    }
+
+    /// Names of the registered custom layer types, in registration order.
+    fn custom_type_names(&self) -> Vec<&str> {
+        self.custom_layers
+            .iter()
+            .map(|t| t.type_name.as_str())
+            .collect()
+    }
+
+    /// Explains why ncnn failed to load text params when [Graph::validate] finds errors in them,
+    /// returning `error` otherwise.
+    fn param_load_error(&self, error: Error) -> Error {
+        let (graph, path) = match &self.param {
+            LoadMethod::Path { path } => {
+                let path = Self::cstr_to_path(path);
+                (path.as_ref().map(Graph::from_path), path)
+            }
+            LoadMethod::ParamMemory { data } => (data.to_str().ok().map(Graph::parse), None),
+            _ => (None, None),
+        };
+        match graph {
+            Some(Ok(graph)) => {
+                let diagnostics: Vec<_> = graph
+                    .validate(&self.custom_type_names())
+                    .into_iter()
+                    .filter(|d| d.severity == param::Severity::Error)
+                    .collect();
+                if diagnostics.is_empty() {
+                    error
+                } else {
+                    Error::InvalidParams { path, diagnostics }
+                }
+            }
+            Some(Err(parse_error @ Error::ParamParse { .. })) => parse_error,
+            _ => error,
+        }
+    }
+
     pub fn new() -> Self {
         NetBuilder::default()
     }
 
-    pub fn set_option(self, opt: &crate::option::Option) -> Self {
+    /// Sets the network option, the built [Net] keeps alive the allocators set on it.
+    pub fn set_option(mut self, opt: &crate::option::Option) -> Self {
         unsafe {
             ncnn_net_set_option(self.ptr.unwrap(), opt.ptr());
         }
+        self.allocators = opt.allocators();
         self
     }
 
-    pub fn set_param_path(mut self, param_path: impl AsRef<OsStr>) -> anyhow::Result<Self> {
+    /// Registers a layer type implemented in Rust, for params loaded by [NetBuilder::build].
+    ///
+    /// `factory` creates a layer for every occurrence of `type_name` in the params. Built-in
+    /// layer types cannot be replaced and each type can only be registered once.
+    pub fn register_custom_layer<L: CustomLayer + 'static>(
+        mut self,
+        type_name: &str,
+        factory: impl Fn() -> L + Send + Sync + 'static,
+    ) -> Result<Self> {
+        ensure!(
+            !self.custom_layers.iter().any(|t| t.type_name == type_name),
+            Error::InvalidArgument(format!("Layer type `{}` already registered", type_name))
+        );
+        let layer_type = CustomLayerType::new(type_name, factory)?;
+        unsafe { layer_type.register(self.ptr.unwrap()) };
+        self.custom_layers.push(layer_type);
+        Ok(self)
+    }
+
+    pub fn set_param_path(mut self, param_path: impl AsRef<OsStr>) -> Result<Self> {
         let path = Self::os_str_to_cstr(param_path.as_ref())
-            .ok_or_else(|| anyhow::anyhow!("Invalid param path"))?;
+            .ok_or_else(|| Error::InvalidArgument("Invalid param path".into()))?;
         self.param = LoadMethod::Path { path };
         Ok(self)
     }
 
-    pub fn set_model_path(mut self, param_path: impl AsRef<OsStr>) -> anyhow::Result<Self> {
+    pub fn set_model_path(mut self, param_path: impl AsRef<OsStr>) -> Result<Self> {
         let path = Self::os_str_to_cstr(param_path.as_ref())
-            .ok_or_else(|| anyhow::anyhow!("Invalid model path"))?;
+            .ok_or_else(|| Error::InvalidArgument("Invalid model path".into()))?;
         self.model = LoadMethod::Path { path };
         Ok(self)
     }
 
+    /// Loads params in the text `.param` format from memory, the data is copied.
+    pub fn set_param_memory(mut self, data: &[u8]) -> Result<Self> {
+        let data =
+            CString::new(data).map_err(|_| Error::InvalidArgument("Invalid param data".into()))?;
+        self.param = LoadMethod::ParamMemory { data };
+        Ok(self)
+    }
+
+    /// Loads params in the text `.param` format from a string, the data is copied.
+    pub fn set_param_str(self, param: &str) -> Result<Self> {
+        self.set_param_memory(param.as_bytes())
+    }
+
+    /// Loads params in the binary `.param.bin` format from a file.
+    pub fn set_param_bin_path(mut self, param_path: impl AsRef<OsStr>) -> Result<Self> {
+        let path = Self::os_str_to_cstr(param_path.as_ref())
+            .ok_or_else(|| Error::InvalidArgument("Invalid param path".into()))?;
+        self.param = LoadMethod::ParamBinPath { path };
+        Ok(self)
+    }
+
+    /// Loads params in the binary `.param.bin` format from memory, the data is copied.
+    ///
+    /// As ncnn reads them without bounds, [NetBuilder::build] first checks that the params are
+    /// complete and fails with [Error::ParamParse] otherwise.
+    pub fn set_param_bin_memory(mut self, data: &[u8]) -> Self {
+        self.param = LoadMethod::ParamBinMemory {
+            data: data.to_vec(),
+        };
+        self
+    }
+
+    /// Loads params in the binary `.param.bin` format from a [DataReader].
+    pub fn set_param_bin_datareader(mut self, datareader: DataReader) -> Self {
+        self.param = LoadMethod::ParamBinDataReader { datareader };
+        self
+    }
+
+    /// Loads model weights from memory, e.g. embedded with `include_bytes!`.
+    ///
+    /// The weights are copied by ncnn while building, so `data` has no alignment requirement and
+    /// building fails if it is shorter than the params need.
+    pub fn set_model_memory(mut self, data: &'a [u8]) -> Self {
+        self.model = LoadMethod::ModelMemory { data };
+        self
+    }
+
+    /// Loads model weights from memory without copying them.
+    ///
+    /// The data must be 4-byte aligned, e.g. embedded with `include_bytes!` into an aligned
+    /// wrapper, and stays borrowed by the built [Net].
+    ///
+    /// # Safety
+    ///
+    /// ncnn reads as many bytes as the layers of the params need without knowing the length of
+    /// `data`, which must hold the complete weights of the params set on this builder.
+    pub unsafe fn set_model_memory_in_place(mut self, data: &'a [u8]) -> Result<Self> {
+        ensure!(
+            data.as_ptr().align_offset(4) == 0,
+            Error::InvalidArgument("Model data must be 4-byte aligned".into())
+        );
+        self.model = LoadMethod::ModelMemoryInPlace { data };
+        Ok(self)
+    }
+
+    /// Loads model weights by memory-mapping a `.bin` file, without reading or copying them.
+    ///
+    /// The mapping is kept alive by the built [Net], so processes loading the same file share
+    /// its page cache.
+    ///
+    /// # Safety
+    ///
+    /// As with [NetBuilder::set_model_memory_in_place], the file must hold the complete weights of the
+    /// params set on this builder. It must not be truncated or modified, by this or another
+    /// process, for the whole life of the built [Net].
+    pub unsafe fn set_model_mmap(mut self, model_path: impl AsRef<Path>) -> Result<Self> {
+        let path = model_path.as_ref().to_path_buf();
+        let file = File::open(&path).map_err(|e| Error::io(&path, e))?;
+        let map = Mmap::map(&file).map_err(|e| Error::io(&path, e))?;
+        self.model = LoadMethod::ModelMmap { map, path };
+        Ok(self)
+    }
+
     pub fn set_param_datareader(mut self, datareader: DataReader) -> Self {
         self.param = LoadMethod::DataReader { datareader };
         self
@@ -65,52 +257,165 @@ impl NetBuilder {
         self
     }
 
-    pub fn build(mut self) -> anyhow::Result<Net> {
+    pub fn build(mut self) -> Result<Net<'a>> {
         let net = self.ptr.take().unwrap();
 
+        let param_error = Error::ParamLoad { path: None };
         match &self.param {
-            LoadMethod::None => anyhow::bail!("No param loading method specified"),
+            LoadMethod::None => {
+                return Err(Error::InvalidArgument(
+                    "No param loading method specified".into(),
+                ))
+            }
             LoadMethod::Path { path } => {
                 if unsafe { ncnn_net_load_param(net, path.as_ptr()) } != 0 {
-                    anyhow::bail!("Error loading params from file");
+                    return Err(self.param_load_error(Error::ParamLoad {
+                        path: Self::cstr_to_path(path),
+                    }));
+                }
+            }
+            LoadMethod::ParamMemory { data } => {
+                if unsafe { ncnn_net_load_param_memory(net, data.as_ptr()) } != 0 {
+                    return Err(self.param_load_error(param_error));
+                }
+            }
+            LoadMethod::ParamBinPath { path } => {
+                if unsafe { ncnn_net_load_param_bin(net, path.as_ptr()) } != 0 {
+                    return Err(Error::ParamLoad {
+                        path: Self::cstr_to_path(path),
+                    });
+                }
+            }
+            LoadMethod::ParamBinMemory { data } => {
+                // Custom layers can be registered after the params are set.
+                let (_, len) = Graph::from_bin_prefix(data, &self.custom_type_names())?;
+                let consumed = unsafe { ncnn_net_load_param_bin_memory(net, data.as_ptr()) };
+                if usize::try_from(consumed).ok() != Some(len) {
+                    return Err(param_error);
+                }
+            }
+            LoadMethod::ParamBinDataReader { datareader } => {
+                if unsafe { ncnn_net_load_param_bin_datareader(net, datareader.ptr()) } != 0 {
+                    return Err(param_error);
                 }
             }
             LoadMethod::DataReader { datareader } => {
                 if unsafe { ncnn_net_load_param_datareader(net, datareader.ptr()) } != 0 {
-                    anyhow::bail!("Error loading params from datareader")
+                    return Err(param_error);
                 }
             }
+            LoadMethod::ModelMemory { .. }
+            | LoadMethod::ModelMemoryInPlace { .. }
+            | LoadMethod::ModelMmap { .. } => {
+                unreachable!("model load method used for params")
+            }
         }
 
+        let graph = self.parse_graph();
         match &self.model {
-            LoadMethod::None => anyhow::bail!("No model loading method specified"),
+            LoadMethod::None => {
+                return Err(Error::InvalidArgument(
+                    "No model loading method specified".into(),
+                ))
+            }
             LoadMethod::Path { path } => {
                 if unsafe { ncnn_net_load_model(net, path.as_ptr()) } != 0 {
-                    anyhow::bail!("Error loading model from file");
+                    return Err(Error::ModelLoad {
+                        path: Self::cstr_to_path(path),
+                        offset: None,
+                    });
                 }
             }
             LoadMethod::DataReader { datareader } => {
                 if unsafe { ncnn_net_load_model_datareader(net, datareader.ptr()) } != 0 {
-                    anyhow::bail!("Error loading model from datareader")
+                    return Err(Error::ModelLoad {
+                        path: None,
+                        offset: None,
+                    });
+                }
+            }
+            LoadMethod::ModelMemory { data } => {
+                // The reader is dropped before `data`, which is borrowed for 'a.
+                let datareader = unsafe { DataReader::from_slice(data) };
+                if unsafe { ncnn_net_load_model_datareader(net, datareader.ptr()) } != 0 {
+                    return Err(Error::ModelLoad {
+                        path: None,
+                        offset: None,
+                    });
                 }
             }
+            LoadMethod::ModelMemoryInPlace { data } => unsafe {
+                Self::load_model_memory(net, graph.as_ref(), data, None)?
+            },
+            LoadMethod::ModelMmap { map, path } => unsafe {
+                Self::load_model_memory(net, graph.as_ref(), map, Some(path))?
+            },
+            LoadMethod::ParamMemory { .. }
+            | LoadMethod::ParamBinPath { .. }
+            | LoadMethod::ParamBinMemory { .. }
+            | LoadMethod::ParamBinDataReader { .. } => {
+                unreachable!("param load method used for model")
+            }
         }
 
-        Ok(Net { ptr: net })
+        let map = match std::mem::replace(&mut self.model, LoadMethod::None) {
+            LoadMethod::ModelMmap { map, .. } => Some(map),
+            _ => None,
+        };
+
+        Ok(Net {
+            ptr: net,
+            graph,
+            _map: map,
+            allocators: std::mem::take(&mut self.allocators),
+            _custom_layers: std::mem::take(&mut self.custom_layers),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Loads weights referenced in place, checking that ncnn consumes the size the layers of
+    /// `graph` need when it is known.
+    ///
+    /// # Safety
+    ///
+    /// `data` must hold the complete weights of the params loaded into `net`.
+    unsafe fn load_model_memory(
+        net: ncnn_net_t,
+        graph: Option<&Graph>,
+        data: &[u8],
+        path: Option<&Path>,
+    ) -> Result<()> {
+        let error = |offset| Error::ModelLoad {
+            path: path.map(Path::to_path_buf),
+            offset,
+        };
+        let size = graph.and_then(|g| g.model_size(data));
+        // ncnn would read past the end of `data`.
+        ensure!(size.is_none_or(|size| size <= data.len()), error(None));
+
+        // Returns the number of bytes consumed whether loading failed or not.
+        let consumed = usize::try_from(ncnn_net_load_model_memory(net, data.as_ptr())).ok();
+        match (consumed, size) {
+            (Some(consumed), Some(size)) if consumed == size => Ok(()),
+            (Some(consumed), None) if consumed <= data.len() => Ok(()),
+            _ => Err(error(consumed)),
+        }
     }
 }
 
-impl Default for NetBuilder {
+impl Default for NetBuilder<'_> {
     fn default() -> Self {
         Self {
             ptr: Some(unsafe { ncnn_net_create() }),
             param: LoadMethod::None,
             model: LoadMethod::None,
+            allocators: Vec::new(),
+            custom_layers: Vec::new(),
         }
     }
 }
 
-impl Drop for NetBuilder {
+impl Drop for NetBuilder<'_> {
     fn drop(&mut self) {
         if let Some(ptr) = self.ptr.take() {
             unsafe { ncnn_net_destroy(ptr) };
@@ -118,21 +423,159 @@ impl Drop for NetBuilder {
     }
 }
 
-pub struct Net {
+/// Index of a blob in a [Net], resolved once with [Net::blob_id] to skip name lookups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlobId(pub(crate) i32);
+
+impl BlobId {
+    /// Index of the blob in the network.
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+pub struct Net<'a> {
     ptr: ncnn_net_t,
+    graph: Option<Graph>,
+    // Weights loaded with NetBuilder::set_model_mmap, referenced in place by ncnn.
+    _map: Option<Mmap>,
+    // Allocators of the option set with NetBuilder::set_option, used while running.
+    allocators: Vec<Allocator>,
+    // Layer types registered with NetBuilder::register_custom_layer, used until ncnn is dropped.
+    #[allow(clippy::vec_box)]
+    _custom_layers: Vec<Box<CustomLayerType>>,
+    _phantom: PhantomData<&'a [u8]>,
 }
 
-unsafe impl Send for Net {}
+unsafe impl Send for Net<'_> {}
 // See: https://github.com/Tencent/ncnn/issues/4774
-unsafe impl Sync for Net {}
+unsafe impl Sync for Net<'_> {}
 
-impl Net {
+impl<'a> Net<'a> {
     pub fn create_extractor(&mut self) -> Extractor<'_> {
-        Extractor::from_ptr(unsafe { ncnn_extractor_create(self.ptr) })
+        Extractor::from_ptr(
+            unsafe { ncnn_extractor_create(self.ptr) },
+            self.allocators.clone(),
+        )
+    }
+
+    fn blob_names(
+        &self,
+        count: unsafe extern "C" fn(ncnn_net_t) -> i32,
+        name: unsafe extern "C" fn(ncnn_net_t, i32) -> *const std::os::raw::c_char,
+    ) -> Vec<String> {
+        (0..unsafe { count(self.ptr) })
+            .map(|i| {
+                let name = unsafe { name(self.ptr, i) };
+                if name.is_null() {
+                    String::new()
+                } else {
+                    unsafe { CStr::from_ptr(name) }
+                        .to_string_lossy()
+                        .into_owned()
+                }
+            })
+            .collect()
+    }
+
+    fn blob_indices(
+        &self,
+        count: unsafe extern "C" fn(ncnn_net_t) -> i32,
+        index: unsafe extern "C" fn(ncnn_net_t, i32) -> i32,
+    ) -> Vec<usize> {
+        (0..unsafe { count(self.ptr) })
+            .map(|i| unsafe { index(self.ptr, i) } as usize)
+            .collect()
+    }
+
+    /// Names of the input blobs, in the order ncnn declares them.
+    pub fn input_names(&self) -> Vec<String> {
+        self.blob_names(ncnn_net_get_input_count, ncnn_net_get_input_name)
+    }
+
+    /// Names of the output blobs, the blobs no layer consumes.
+    pub fn output_names(&self) -> Vec<String> {
+        self.blob_names(ncnn_net_get_output_count, ncnn_net_get_output_name)
+    }
+
+    /// Blob indices of the inputs, matching [Net::input_names].
+    pub fn input_indices(&self) -> Vec<usize> {
+        self.blob_indices(ncnn_net_get_input_count, ncnn_net_get_input_index)
+    }
+
+    /// Blob indices of the outputs, matching [Net::output_names].
+    pub fn output_indices(&self) -> Vec<usize> {
+        self.blob_indices(ncnn_net_get_output_count, ncnn_net_get_output_index)
+    }
+
+    /// Params graph of the network.
+    ///
+    /// The ncnn C API cannot enumerate the layers of a loaded net, so the params are parsed again
+    /// from their source. Nets loaded from a [DataReader] have no graph and return
+    /// [Error::GraphUnavailable].
+    pub fn graph(&self) -> Result<&Graph> {
+        self.graph.as_ref().ok_or(Error::GraphUnavailable)
+    }
+
+    /// Layers of the network in execution order, see [Net::graph].
+    pub fn layers(&self) -> Result<&[param::Layer]> {
+        self.graph().map(|g| g.layers.as_slice())
+    }
+
+    /// Index of the blob named `name`.
+    ///
+    /// Without a [Net::graph] only input and output blobs can be found, other names return
+    /// [Error::GraphUnavailable].
+    pub fn blob_index(&self, name: &str) -> Result<usize> {
+        match &self.graph {
+            Some(graph) => graph
+                .blob_index(name)
+                .ok_or_else(|| Error::UnknownBlob(name.to_string())),
+            None => self
+                .input_names()
+                .into_iter()
+                .zip(self.input_indices())
+                .chain(self.output_names().into_iter().zip(self.output_indices()))
+                .find(|(n, _)| n == name)
+                .map(|(_, i)| i)
+                .ok_or(Error::GraphUnavailable),
+        }
+    }
+
+    /// Resolves the blob named `name` for [Extractor::input_index] and [Extractor::extract_index].
+    ///
+    /// Without a [Net::graph] only input and output blobs can be found, see [Net::blob_index].
+    pub fn blob_id(&self, name: &str) -> Result<BlobId> {
+        let index = self.blob_index(name)?;
+        i32::try_from(index)
+            .map(BlobId)
+            .map_err(|_| Error::InvalidBlobIndex(index))
+    }
+
+    /// Name of the blob at `index`.
+    ///
+    /// Without a [Net::graph] only input and output blobs can be found, other indices return
+    /// [Error::GraphUnavailable].
+    pub fn blob_name(&self, index: usize) -> Result<String> {
+        match &self.graph {
+            Some(graph) => graph
+                .blobs
+                .get(index)
+                .map(|b| b.name.clone())
+                .ok_or(Error::InvalidBlobIndex(index)),
+            None => self
+                .input_indices()
+                .into_iter()
+                .zip(self.input_names())
+                .chain(self.output_indices().into_iter().zip(self.output_names()))
+                .find(|(i, _)| *i == index)
+                .map(|(_, n)| n)
+                .ok_or(Error::GraphUnavailable),
+        }
     }
 }
 
-impl Drop for Net {
+impl Drop for Net<'_> {
     fn drop(&mut self) {
         unsafe {
             ncnn_net_destroy(self.ptr);
@@ -164,6 +607,288 @@ mod tests {
             .expect_err("Expected files to not be found");
     }
 
+    #[test]
+    fn load_not_exist_param_bin() {
+        let _ = NetBuilder::new()
+            .set_param_bin_path("not_exist.param.bin")
+            .unwrap()
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .map(|_| ())
+            .expect_err("Expected files to not be found");
+    }
+
+    #[test]
+    fn load_from_memory() {
+        #[repr(align(4))]
+        struct Aligned([u8; 8]);
+        let model = Aligned([0; 8]);
+
+        let builder = NetBuilder::new()
+            .set_param_str("7767517\n2 2\nInput data 0 1 data 0=4\nReLU relu 1 1 data output\n")
+            .unwrap();
+        // ReLU has no weights, trailing data is left unread.
+        let mut net = unsafe { builder.set_model_memory_in_place(&model.0) }
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(-1.0);
+        let mut output = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!(&[0.0; 4], output.as_slice::<f32>().unwrap());
+
+        assert!(unsafe { NetBuilder::new().set_model_memory_in_place(&model.0[1..]) }.is_err());
+
+        // Copied weights need no alignment.
+        let mut scale = vec![0u8];
+        scale.extend([1.0f32, 2.0, 3.0, 4.0].iter().flat_map(|v| v.to_le_bytes()));
+        let param = "7767517\n2 2\nInput data 0 1 data 0=4\nScale scale 1 1 data output 0=4\n";
+        let mut net = NetBuilder::new()
+            .set_param_str(param)
+            .unwrap()
+            .set_model_memory(&scale[1..])
+            .build()
+            .unwrap();
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(2.0);
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!(&[2.0, 4.0, 6.0, 8.0], output.as_slice::<f32>().unwrap());
+
+        let truncated = NetBuilder::new()
+            .set_param_str(param)
+            .unwrap()
+            .set_model_memory(&scale[1..9])
+            .build();
+        assert!(truncated.is_err());
+
+        // Checked against the size Scale needs before ncnn reads past the end.
+        let truncated = NetBuilder::new().set_param_str(param).unwrap();
+        let truncated = unsafe { truncated.set_model_memory_in_place(&model.0) }
+            .unwrap()
+            .build();
+        assert!(matches!(truncated, Err(Error::ModelLoad { .. })));
+        assert!(NetBuilder::new().set_param_memory(b"7767517\0").is_err());
+    }
+
+    #[test]
+    fn load_param_bin_memory() {
+        let bin =
+            Graph::parse("7767517\n2 2\nInput data 0 1 data 0=4\nReLU relu 1 1 data output\n")
+                .unwrap()
+                .to_bin()
+                .unwrap();
+
+        let net = NetBuilder::new()
+            .set_param_bin_memory(&bin)
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .unwrap();
+        assert_eq!(1, net.blob_index("1").unwrap());
+
+        let err = NetBuilder::new()
+            .set_param_bin_memory(&bin[..bin.len() - 4])
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, Error::ParamParse { .. }), "{}", err);
+    }
+
+    #[test]
+    fn explain_param_errors() {
+        let err = NetBuilder::new()
+            .set_param_str(
+                "7767517\n2 2\nInput data 0 1 data 0=4\nFrobnicate frob 1 1 data output\n",
+            )
+            .unwrap()
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .map(|_| ())
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidParams { .. }), "{}", err);
+        assert_eq!(
+            "Invalid params: line 4: error: Unknown layer type `Frobnicate`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn load_from_mmap() {
+        let path = std::env::temp_dir().join(format!("ncnn-rs-mmap-{}.bin", std::process::id()));
+        let scale: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        std::fs::write(&path, scale).unwrap();
+
+        let builder = NetBuilder::new()
+            .set_param_str(
+                "7767517\n2 2\nInput data 0 1 data 0=4\nScale scale 1 1 data output 0=4\n",
+            )
+            .unwrap();
+        let mut net = unsafe { builder.set_model_mmap(&path) }
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(2.0);
+        let mut output = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!(&[2.0, 4.0, 6.0, 8.0], output.as_slice::<f32>().unwrap());
+
+        drop(ex);
+        drop(net);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(unsafe { NetBuilder::new().set_model_mmap("not_exist.bin") }.is_err());
+    }
+
+    #[test]
+    fn introspection() {
+        let param = "7767517\n3 3\nInput data 0 1 data 0=4\nReLU relu 1 1 data hidden\nReLU relu2 1 1 hidden output\n";
+
+        let net = NetBuilder::new()
+            .set_param_str(param)
+            .unwrap()
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .unwrap();
+
+        assert_eq!(vec!["data"], net.input_names());
+        assert_eq!(vec!["output"], net.output_names());
+        assert_eq!(vec![0], net.input_indices());
+        assert_eq!(vec![2], net.output_indices());
+        assert_eq!(1, net.blob_index("hidden").unwrap());
+        assert_eq!("hidden", net.blob_name(1).unwrap());
+        assert!(matches!(
+            net.blob_index("missing"),
+            Err(Error::UnknownBlob(_))
+        ));
+        assert!(matches!(net.blob_name(3), Err(Error::InvalidBlobIndex(3))));
+
+        let layers = net.layers().unwrap();
+        assert_eq!(3, layers.len());
+        assert_eq!("relu2", layers[2].name);
+
+        let net = NetBuilder::new()
+            .set_param_datareader(DataReader::from_reader(std::io::Cursor::new(param)))
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .unwrap();
+
+        assert!(matches!(net.layers(), Err(Error::GraphUnavailable)));
+        assert_eq!(2, net.blob_index("output").unwrap());
+        assert!(matches!(
+            net.blob_index("hidden"),
+            Err(Error::GraphUnavailable)
+        ));
+        assert_eq!("data", net.blob_name(0).unwrap());
+    }
+
+    #[test]
+    fn extract_by_id() {
+        let mut net = NetBuilder::new()
+            .set_param_str("7767517\n2 2\nInput data 0 1 data 0=4\nReLU relu 1 1 data output\n")
+            .unwrap()
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .unwrap();
+
+        let data = net.blob_id("data").unwrap();
+        let output = net.blob_id("output").unwrap();
+        assert_eq!(1, output.index());
+        assert!(net.blob_id("missing").is_err());
+
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(-1.0);
+        let mut output_mat = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input_index(data, &input).unwrap();
+        ex.extract_index(output, &mut output_mat).unwrap();
+        assert_eq!(&[0.0; 4], output_mat.as_slice::<f32>().unwrap());
+
+        assert!(ex.extract_index(BlobId(7), &mut output_mat).is_err());
+    }
+
+    #[test]
+    fn extract_many() {
+        let mut net = NetBuilder::new()
+            .set_param_str("7767517\n3 4\nInput data 0 1 data 0=4\nSplit split 1 2 data a b\nReLU relu 1 1 b output\n")
+            .unwrap()
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .unwrap();
+
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(-1.0);
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+
+        let mut output = crate::Mat::new();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!(&[0.0; 4], output.as_slice::<f32>().unwrap());
+
+        let outputs = ex.extract_many(&["a", "output"]).unwrap();
+        assert_eq!(2, outputs.len());
+        assert_eq!(&[-1.0; 4], outputs[0].as_slice::<f32>().unwrap());
+        assert_eq!(&[0.0; 4], outputs[1].as_slice::<f32>().unwrap());
+
+        assert!(ex.extract_many(&["output", "missing"]).is_err());
+        assert!(matches!(
+            ex.extract_many(&["a", "output", "a"]),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn outlives_allocators() {
+        let blob_alloc = Allocator::new();
+        let workspace_alloc = Allocator::new();
+        let mut opt = crate::Option::new();
+        opt.set_blob_allocator(&blob_alloc)
+            .set_workspace_allocator(&workspace_alloc);
+        let mut net = NetBuilder::new()
+            .set_option(&opt)
+            .set_param_str("7767517\n2 2\nInput data 0 1 data 0=4\nReLU relu 1 1 data output\n")
+            .unwrap()
+            .set_model_datareader(DataReader::empty())
+            .build()
+            .unwrap();
+        drop((opt, blob_alloc, workspace_alloc));
+
+        let mut input = crate::Mat::new_1d(4, None).unwrap();
+        input.fill(-1.0);
+        let mut output = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        drop(ex);
+
+        // The input blob shares the data of the input matrix.
+        let input_alloc = Allocator::new();
+        let mut shared_input = crate::Mat::new_1d(4, Some(&input_alloc)).unwrap();
+        shared_input.fill(-1.0);
+        let mut data = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &shared_input).unwrap();
+        ex.extract("data", &mut data).unwrap();
+        drop(ex);
+        drop((shared_input, input_alloc));
+
+        drop(net);
+        assert_eq!(&[0.0; 4], output.as_slice::<f32>().unwrap());
+        assert_eq!(&[-1.0; 4], data.as_slice::<f32>().unwrap());
+    }
+
     #[test]
     fn check_sync_send() {
         assert!(is_send::<Net>());
@@ -1,8 +1,41 @@
+use crate::allocator::Allocator;
+use core::fmt;
+use core::mem::ManuallyDrop;
 use ncnn_bind::*;
 use std::os::raw::c_int;
 
+/// Defines the setter and getter of boolean flags, documented from a description of what ncnn
+/// does when the flag is enabled. Further docs go to the setter.
+macro_rules! flag_accessors {
+    ($(
+        $(#[$doc:meta])*
+        $get:ident, $set:ident => $c_get:ident, $c_set:ident: $what:literal;
+    )*) => {
+        $(
+            #[doc = concat!("Sets whether ncnn ", $what, ".")]
+            #[doc = ""]
+            $(#[$doc])*
+            pub fn $set(&mut self, enabled: bool) -> &mut Self {
+                unsafe { $c_set(self.ptr, enabled as c_int) };
+                self
+            }
+
+            #[doc = concat!("Whether ncnn ", $what, ".")]
+            pub fn $get(&self) -> bool {
+                unsafe { $c_get(self.ptr) != 0 }
+            }
+        )*
+    };
+}
+
+/// Options controlling how networks and layers run.
+///
+/// Setters return the option itself so they can be chained. Options set on a
+/// [crate::NetBuilder] are copied, later changes have no effect on the built network.
 pub struct Option {
     ptr: ncnn_option_t,
+    blob_allocator: std::option::Option<Allocator>,
+    workspace_allocator: std::option::Option<Allocator>,
 }
 
 unsafe impl Send for Option {}
@@ -12,39 +45,193 @@ impl Option {
         Self::default()
     }
 
-    pub fn set_num_threads(&mut self, num_threads: u32) {
+    pub fn set_num_threads(&mut self, num_threads: u32) -> &mut Self {
         unsafe {
             ncnn_option_set_num_threads(self.ptr, num_threads as c_int);
         }
+        self
     }
 
     pub fn get_num_threads(&self) -> u32 {
         unsafe { ncnn_option_get_num_threads(self.ptr) as u32 }
     }
 
-    pub fn set_vulkan_compute(&mut self, enabled: bool) {
+    pub fn set_vulkan_compute(&mut self, enabled: bool) -> &mut Self {
         unsafe {
             ncnn_option_set_use_vulkan_compute(self.ptr, enabled as c_int);
         }
+        self
     }
 
     pub fn get_vulkan_compute(&self) -> bool {
         unsafe { ncnn_option_get_use_vulkan_compute(self.ptr) != 0 }
     }
 
+    /// Recycles memory of a local pool allocator between runs, enabled by default.
+    pub fn set_local_pool_allocator(&mut self, enabled: bool) -> &mut Self {
+        unsafe {
+            ncnn_option_set_use_local_pool_allocator(self.ptr, enabled as c_int);
+        }
+        self
+    }
+
+    /// Recycles memory of a local pool allocator between runs, enabled by default.
+    pub fn get_local_pool_allocator(&self) -> bool {
+        unsafe { ncnn_option_get_use_local_pool_allocator(self.ptr) != 0 }
+    }
+
+    /// Allocator for blobs produced while running, including extracted outputs.
+    ///
+    /// The option, and networks or matrices created with it, keep the allocator alive.
+    pub fn set_blob_allocator(&mut self, allocator: &Allocator) -> &mut Self {
+        unsafe {
+            ncnn_option_set_blob_allocator(self.ptr, allocator.ptr());
+        }
+        self.blob_allocator = Some(allocator.clone());
+        self
+    }
+
+    /// Allocator for temporary memory of layers while running.
+    ///
+    /// The option, and networks created with it, keep the allocator alive.
+    pub fn set_workspace_allocator(&mut self, allocator: &Allocator) -> &mut Self {
+        unsafe {
+            ncnn_option_set_workspace_allocator(self.ptr, allocator.ptr());
+        }
+        self.workspace_allocator = Some(allocator.clone());
+        self
+    }
+
+    /// Time in milliseconds OpenMP threads busy-wait for more work before sleeping, 20 by default.
+    pub fn set_openmp_blocktime(&mut self, blocktime: u32) -> &mut Self {
+        unsafe {
+            ncnn_bind_option_set_openmp_blocktime(
+                self.ptr,
+                blocktime.min(c_int::MAX as u32) as c_int,
+            )
+        };
+        self
+    }
+
+    /// Time in milliseconds OpenMP threads busy-wait for more work before sleeping, 20 by default.
+    pub fn get_openmp_blocktime(&self) -> u32 {
+        unsafe { ncnn_bind_option_get_openmp_blocktime(self.ptr).max(0) as u32 }
+    }
+
+    /// Denormal flushing mode: 0 disables it, 1 sets denormals-are-zero, 2 flush-to-zero and
+    /// 3, the default, both.
+    pub fn set_flush_denormals(&mut self, mode: u32) -> &mut Self {
+        unsafe { ncnn_bind_option_set_flush_denormals(self.ptr, mode.min(3) as c_int) };
+        self
+    }
+
+    /// Denormal flushing mode, see [Option::set_flush_denormals].
+    pub fn get_flush_denormals(&self) -> u32 {
+        unsafe { ncnn_bind_option_get_flush_denormals(self.ptr).max(0) as u32 }
+    }
+
+    flag_accessors! {
+        /// Enabled by default.
+        get_lightmode, set_lightmode =>
+            ncnn_bind_option_get_lightmode, ncnn_bind_option_set_lightmode:
+            "recycles intermediate blobs during inference";
+        /// Enabled by default, must be set before loading params and weights.
+        get_winograd_convolution, set_winograd_convolution =>
+            ncnn_bind_option_get_use_winograd_convolution,
+            ncnn_bind_option_set_use_winograd_convolution:
+            "uses winograd convolution for 3x3 stride 1 kernels";
+        /// Enabled by default, must be set before loading params and weights.
+        get_sgemm_convolution, set_sgemm_convolution =>
+            ncnn_bind_option_get_use_sgemm_convolution, ncnn_bind_option_set_use_sgemm_convolution:
+            "uses sgemm convolution for 1x1 stride 1 kernels";
+        /// Enabled by default, must be set before loading params and weights.
+        get_int8_inference, set_int8_inference =>
+            ncnn_bind_option_get_use_int8_inference, ncnn_bind_option_set_use_int8_inference:
+            "runs quantized models through the low precision int8 path";
+        get_bf16_storage, set_bf16_storage =>
+            ncnn_bind_option_get_use_bf16_storage, ncnn_bind_option_set_use_bf16_storage:
+            "stores data as bf16 where supported";
+        get_fp16_packed, set_fp16_packed =>
+            ncnn_bind_option_get_use_fp16_packed, ncnn_bind_option_set_use_fp16_packed:
+            "uses packed fp16 data where supported";
+        get_fp16_storage, set_fp16_storage =>
+            ncnn_bind_option_get_use_fp16_storage, ncnn_bind_option_set_use_fp16_storage:
+            "stores data as fp16 where supported";
+        get_fp16_arithmetic, set_fp16_arithmetic =>
+            ncnn_bind_option_get_use_fp16_arithmetic, ncnn_bind_option_set_use_fp16_arithmetic:
+            "computes in fp16 where supported";
+        get_int8_packed, set_int8_packed =>
+            ncnn_bind_option_get_use_int8_packed, ncnn_bind_option_set_use_int8_packed:
+            "uses packed int8 data where supported";
+        get_int8_storage, set_int8_storage =>
+            ncnn_bind_option_get_use_int8_storage, ncnn_bind_option_set_use_int8_storage:
+            "stores data as int8 where supported";
+        get_int8_arithmetic, set_int8_arithmetic =>
+            ncnn_bind_option_get_use_int8_arithmetic, ncnn_bind_option_set_use_int8_arithmetic:
+            "computes in int8 where supported";
+        /// Enabled by default, must be set before loading params and weights.
+        get_packing_layout, set_packing_layout =>
+            ncnn_bind_option_get_use_packing_layout, ncnn_bind_option_set_use_packing_layout:
+            "uses the SIMD friendly packed memory layout";
+    }
+
     pub(crate) fn ptr(&self) -> ncnn_option_t {
         self.ptr
     }
+
+    /// Wraps an option owned by ncnn, which must outlive the wrapper and never be dropped by it.
+    pub(crate) unsafe fn borrow_raw(ptr: ncnn_option_t) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self {
+            ptr,
+            blob_allocator: None,
+            workspace_allocator: None,
+        })
+    }
+
+    /// Allocators set on the option, to be kept alive by whatever copies it.
+    pub(crate) fn allocators(&self) -> Vec<Allocator> {
+        self.blob_allocator
+            .iter()
+            .chain(&self.workspace_allocator)
+            .cloned()
+            .collect()
+    }
 }
 
 impl Default for Option {
     fn default() -> Self {
         Self {
             ptr: unsafe { ncnn_option_create() },
+            blob_allocator: None,
+            workspace_allocator: None,
         }
     }
 }
 
+impl fmt::Debug for Option {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Option")
+            .field("num_threads", &self.get_num_threads())
+            .field("vulkan_compute", &self.get_vulkan_compute())
+            .field("local_pool_allocator", &self.get_local_pool_allocator())
+            .field("openmp_blocktime", &self.get_openmp_blocktime())
+            .field("flush_denormals", &self.get_flush_denormals())
+            .field("lightmode", &self.get_lightmode())
+            .field("winograd_convolution", &self.get_winograd_convolution())
+            .field("sgemm_convolution", &self.get_sgemm_convolution())
+            .field("int8_inference", &self.get_int8_inference())
+            .field("bf16_storage", &self.get_bf16_storage())
+            .field("fp16_packed", &self.get_fp16_packed())
+            .field("fp16_storage", &self.get_fp16_storage())
+            .field("fp16_arithmetic", &self.get_fp16_arithmetic())
+            .field("int8_packed", &self.get_int8_packed())
+            .field("int8_storage", &self.get_int8_storage())
+            .field("int8_arithmetic", &self.get_int8_arithmetic())
+            .field("packing_layout", &self.get_packing_layout())
+            .finish()
+    }
+}
+
 impl Drop for Option {
     fn drop(&mut self) {
         unsafe {
@@ -62,4 +249,36 @@ mod tests {
         opt.set_num_threads(4);
         assert_eq!(4, opt.get_num_threads());
     }
+
+    #[test]
+    fn all_flags() {
+        use crate::option::*;
+        let mut opt = Option::new();
+        assert!(opt.get_lightmode());
+        assert_eq!(20, opt.get_openmp_blocktime());
+        assert_eq!(3, opt.get_flush_denormals());
+
+        opt.set_num_threads(2)
+            .set_lightmode(false)
+            .set_openmp_blocktime(0)
+            .set_flush_denormals(7)
+            .set_winograd_convolution(false)
+            .set_sgemm_convolution(false)
+            .set_fp16_storage(false)
+            .set_bf16_storage(true)
+            .set_local_pool_allocator(false);
+
+        assert_eq!(2, opt.get_num_threads());
+        assert!(!opt.get_lightmode());
+        assert_eq!(0, opt.get_openmp_blocktime());
+        assert_eq!(3, opt.get_flush_denormals());
+        assert!(!opt.get_winograd_convolution());
+        assert!(!opt.get_sgemm_convolution());
+        assert!(!opt.get_fp16_storage());
+        assert!(opt.get_bf16_storage());
+        assert!(!opt.get_local_pool_allocator());
+
+        let debug = format!("{:?}", opt);
+        assert!(debug.contains("lightmode: false"), "{}", debug);
+    }
 }
@@ -0,0 +1,287 @@
+use super::layers::is_float_param;
+use super::types::{layer_type_index, layer_type_name};
+use super::{Blob, Graph, Layer, ParamMap, ParamValue, ARRAY_ID_OFFSET, MAGIC};
+use crate::error::{ensure, Error, Result};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// Marks the end of a layer's parameters.
+const END_OF_PARAMS: i32 = -233;
+
+/// Set in the type index of layers registered through custom layer factories.
+const CUSTOM_LAYER_BIT: i32 = 1 << 8;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self) -> Result<i32> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + 4)
+            .ok_or_else(|| Error::param_at_offset(self.offset, "Unexpected end of data"))?;
+        self.offset += 4;
+        Ok(i32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_count(&mut self, name: &str) -> Result<usize> {
+        let offset = self.offset;
+        let value = self.read()?;
+        usize::try_from(value)
+            .map_err(|_| Error::param_at_offset(offset, format!("Invalid {} {}", name, value)))
+    }
+}
+
+/// Reads the params of a layer. The binary format stores raw 4-byte values without their type,
+/// which is taken from the typed params of the layer type and kept raw for other ids.
+fn read_params(reader: &mut Reader, type_name: &str) -> Result<ParamMap> {
+    let mut params = ParamMap::new();
+    loop {
+        let id = reader.read()?;
+        if id == END_OF_PARAMS {
+            break;
+        }
+
+        let value = if id <= ARRAY_ID_OFFSET {
+            let len = reader.read_count("array length")?;
+            let values = (0..len)
+                .map(|_| reader.read())
+                .collect::<Result<Vec<_>>>()?;
+            match is_float_param(type_name, id) {
+                Some(true) => ParamValue::FloatArray(
+                    values.iter().map(|v| f32::from_bits(*v as u32)).collect(),
+                ),
+                Some(false) => ParamValue::IntArray(values),
+                None => ParamValue::RawArray(values),
+            }
+        } else {
+            let value = reader.read()?;
+            match is_float_param(type_name, id) {
+                Some(true) => ParamValue::Float(f32::from_bits(value as u32)),
+                Some(false) => ParamValue::Int(value),
+                None => ParamValue::Raw(value),
+            }
+        };
+        params.set(id, value);
+    }
+
+    Ok(params)
+}
+
+/// Reads a graph, custom layer types being looked up in `custom_types` in registration order,
+/// and returns it along with the number of bytes read.
+pub(super) fn read(data: &[u8], custom_types: &[&str]) -> Result<(Graph, usize)> {
+    let mut reader = Reader { data, offset: 0 };
+
+    let magic = reader.read()?;
+    ensure!(
+        magic == MAGIC,
+        Error::param_at_offset(0, format!("Expected magic {}, found {}", MAGIC, magic))
+    );
+
+    let declared_layer_count = reader.read_count("layer count")?;
+    let declared_blob_count = reader.read_count("blob count")?;
+
+    // Names are not stored in the binary format, blobs are named after their index.
+    let mut blobs = (0..declared_blob_count)
+        .map(|i| Blob {
+            name: i.to_string(),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+    let mut layers = Vec::with_capacity(declared_layer_count);
+
+    for layer_index in 0..declared_layer_count {
+        let offset = reader.offset;
+        let type_index = reader.read()?;
+        let type_name = if type_index & CUSTOM_LAYER_BIT != 0 {
+            let custom_index = type_index & !CUSTOM_LAYER_BIT;
+            usize::try_from(custom_index)
+                .ok()
+                .and_then(|i| custom_types.get(i).copied())
+                .ok_or_else(|| {
+                    Error::param_at_offset(
+                        offset,
+                        format!("Custom layer type {} is not registered", custom_index),
+                    )
+                })?
+        } else {
+            layer_type_name(type_index).ok_or_else(|| {
+                Error::param_at_offset(offset, format!("Unknown layer type {}", type_index))
+            })?
+        };
+
+        let bottom_count = reader.read_count("bottom count")?;
+        let top_count = reader.read_count("top count")?;
+
+        let read_blob = |reader: &mut Reader| -> Result<usize> {
+            let offset = reader.offset;
+            let index = reader.read_count("blob index")?;
+            ensure!(
+                index < blobs.len(),
+                Error::param_at_offset(offset, format!("Blob index {} out of range", index))
+            );
+            Ok(index)
+        };
+
+        let bottoms = (0..bottom_count)
+            .map(|_| read_blob(&mut reader))
+            .collect::<Result<Vec<_>>>()?;
+        let tops = (0..top_count)
+            .map(|_| read_blob(&mut reader))
+            .collect::<Result<Vec<_>>>()?;
+
+        for bottom in &bottoms {
+            blobs[*bottom].consumers.push(layer_index);
+        }
+        for top in &tops {
+            blobs[*top].producer = Some(layer_index);
+        }
+
+        layers.push(Layer {
+            type_name: type_name.to_string(),
+            name: format!("{}_{}", type_name, layer_index),
+            bottoms,
+            tops,
+            params: read_params(&mut reader, type_name)?,
+            line: None,
+        });
+    }
+
+    let graph = Graph {
+        layers,
+        blobs,
+        declared_layer_count,
+        declared_blob_count,
+        surplus_lines: Vec::new(),
+    };
+    Ok((graph, reader.offset))
+}
+
+pub(super) fn write(graph: &Graph) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut push = |v: i32| out.extend_from_slice(&v.to_le_bytes());
+
+    push(MAGIC);
+    push(graph.layers.len() as i32);
+    push(graph.blobs.len() as i32);
+
+    for layer in &graph.layers {
+        let type_index = layer_type_index(&layer.type_name)
+            .ok_or_else(|| Error::UnknownLayerType(layer.type_name.clone()))?;
+
+        push(type_index);
+        push(layer.bottoms.len() as i32);
+        push(layer.tops.len() as i32);
+        layer
+            .bottoms
+            .iter()
+            .chain(&layer.tops)
+            .for_each(|b| push(*b as i32));
+
+        for (id, value) in layer.params.iter() {
+            push(id);
+            match value {
+                ParamValue::Int(v) | ParamValue::Raw(v) => push(*v),
+                ParamValue::Float(v) => push(v.to_bits() as i32),
+                ParamValue::IntArray(v) | ParamValue::RawArray(v) => {
+                    push(v.len() as i32);
+                    v.iter().for_each(|v| push(*v));
+                }
+                ParamValue::FloatArray(v) => {
+                    push(v.len() as i32);
+                    v.iter().for_each(|v| push(v.to_bits() as i32));
+                }
+            }
+        }
+        push(END_OF_PARAMS);
+    }
+
+    Ok(out)
+}
+
+/// Turns a layer or blob name into an upper case Rust identifier.
+fn identifier(prefix: &str, name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{}_{}", prefix, name)
+}
+
+pub(super) fn id_table(graph: &Graph, module: &str) -> String {
+    let mut out = String::new();
+    let mut used = HashSet::new();
+    let mut unique = |ident: String, index: usize| {
+        if used.insert(ident.clone()) {
+            ident
+        } else {
+            format!("{}_{}", ident, index)
+        }
+    };
+
+    writeln!(out, "#[allow(dead_code)]").unwrap();
+    writeln!(out, "pub mod {} {{", module).unwrap();
+    for (i, layer) in graph.layers.iter().enumerate() {
+        let ident = unique(identifier("LAYER", &layer.name), i);
+        writeln!(out, "    pub const {}: usize = {};", ident, i).unwrap();
+    }
+    for (i, blob) in graph.blobs.iter().enumerate() {
+        let ident = unique(identifier("BLOB", &blob.name), i);
+        writeln!(out, "    pub const {}: usize = {};", ident, i).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_errors() {
+        assert!(read(&[], &[]).is_err());
+        assert!(read(&1i32.to_le_bytes(), &[]).is_err());
+
+        let truncated = [MAGIC, 1, 1, 16, 0, 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert!(read(&truncated, &[]).is_err());
+
+        let out_of_range = [MAGIC, 1, 1, 16, 0, 1, 5, END_OF_PARAMS]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert!(read(&out_of_range, &[]).is_err());
+    }
+
+    #[test]
+    fn custom_layers() {
+        let mut data = [MAGIC, 1, 1, CUSTOM_LAYER_BIT | 1, 0, 1, 0, END_OF_PARAMS]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert!(read(&data, &["Double"]).is_err());
+
+        data.extend_from_slice(&[0; 4]);
+        let (graph, len) = read(&data, &["Double", "Triple"]).unwrap();
+        assert_eq!("Triple", graph.layers[0].type_name);
+        assert_eq!(data.len() - 4, len);
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!("BLOB_CONV2_1_DW", identifier("BLOB", "conv2_1/dw"));
+        assert_eq!("LAYER_0_25", identifier("LAYER", "0_25"));
+    }
+}
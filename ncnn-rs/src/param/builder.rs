@@ -0,0 +1,443 @@
+use super::{
+    Blob, ConvolutionParams, Graph, InnerProductParams, InputParams, Layer, LayerParams, ParamMap,
+    PoolingParams, ReLUParams,
+};
+use crate::datareader::DataReader;
+use crate::error::{ensure, Error, Result};
+use crate::modelbin::WeightType;
+use crate::net::{Net, NetBuilder};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Pooling operation of [GraphBuilder::pooling].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolingType {
+    Max,
+    Average,
+}
+
+struct PendingLayer {
+    type_name: String,
+    name: String,
+    bottoms: Vec<String>,
+    tops: Vec<String>,
+    params: ParamMap,
+}
+
+/// Builds a network in code, wiring layers through blob names.
+///
+/// Blobs consumed by several layers get a `Split` layer, as done by ncnn's converters. The
+/// result is available as a [Graph], `.param` text and `.bin` weights, or a live [Net].
+#[derive(Default)]
+pub struct GraphBuilder {
+    layers: Vec<PendingLayer>,
+    blobs: HashSet<String>,
+    weights: Vec<u8>,
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer of any type, consuming existing blobs and producing new ones.
+    ///
+    /// `weights` are written in order, in the format the layer reads them with. Fails if a
+    /// bottom blob has not been produced yet or a top blob already has been.
+    pub fn layer(
+        &mut self,
+        type_name: &str,
+        name: &str,
+        bottoms: &[&str],
+        tops: &[&str],
+        params: ParamMap,
+        weights: &[(WeightType, &[f32])],
+    ) -> Result<&mut Self> {
+        for bottom in bottoms {
+            ensure!(
+                self.blobs.contains(*bottom),
+                Error::UnknownBlob(bottom.to_string())
+            );
+        }
+        for (i, top) in tops.iter().enumerate() {
+            ensure!(
+                !self.blobs.contains(*top) && !tops[..i].contains(top),
+                Error::InvalidArgument(format!("Blob `{}` is produced twice", top))
+            );
+        }
+
+        self.blobs.extend(tops.iter().map(|top| top.to_string()));
+        self.layers.push(PendingLayer {
+            type_name: type_name.to_string(),
+            name: name.to_string(),
+            bottoms: bottoms.iter().map(|b| b.to_string()).collect(),
+            tops: tops.iter().map(|t| t.to_string()).collect(),
+            params,
+        });
+        for (weight_type, data) in weights {
+            if *weight_type == WeightType::Auto {
+                // A zero tag announces raw float32 data.
+                self.weights.extend_from_slice(&[0; 4]);
+            }
+            self.weights
+                .extend(data.iter().flat_map(|value| value.to_le_bytes()));
+        }
+        Ok(self)
+    }
+
+    /// Adds a layer of the type of `params`, as [GraphBuilder::layer] does.
+    pub fn add_layer<P: LayerParams>(
+        &mut self,
+        name: &str,
+        bottoms: &[&str],
+        tops: &[&str],
+        params: &P,
+        weights: &[(WeightType, &[f32])],
+    ) -> Result<&mut Self> {
+        self.layer(
+            P::TYPE_NAME,
+            name,
+            bottoms,
+            tops,
+            params.to_params(),
+            weights,
+        )
+    }
+
+    /// Adds an input blob of `w`, `h` and `c` elements, 0 for unused dimensions.
+    pub fn input(&mut self, name: &str, w: i32, h: i32, c: i32) -> Result<&mut Self> {
+        let params = InputParams {
+            w,
+            h,
+            c,
+            ..Default::default()
+        };
+        self.add_layer(name, &[], &[name], &params, &[])
+    }
+
+    /// Adds a convolution, `weights` being laid out as output channel, input channel, row and
+    /// column.
+    ///
+    /// `bias_term` and `weight_data_size` of `params` are set from `weights` and `bias`.
+    pub fn convolution(
+        &mut self,
+        name: &str,
+        bottom: &str,
+        top: &str,
+        params: &ConvolutionParams,
+        weights: &[f32],
+        bias: Option<&[f32]>,
+    ) -> Result<&mut Self> {
+        let params = ConvolutionParams {
+            bias_term: bias.is_some() as i32,
+            weight_data_size: weights.len() as i32,
+            ..params.clone()
+        };
+        let bias = bias.map(|bias| (WeightType::Float32, bias));
+        let weights: Vec<_> = [(WeightType::Auto, weights)]
+            .into_iter()
+            .chain(bias)
+            .collect();
+        self.add_layer(name, &[bottom], &[top], &params, &weights)
+    }
+
+    /// Adds a square pooling window.
+    pub fn pooling(
+        &mut self,
+        name: &str,
+        bottom: &str,
+        top: &str,
+        pooling_type: PoolingType,
+        kernel: i32,
+        stride: i32,
+    ) -> Result<&mut Self> {
+        let params = PoolingParams {
+            pooling_type: (pooling_type == PoolingType::Average) as i32,
+            kernel_w: kernel,
+            kernel_h: kernel,
+            stride_w: stride,
+            stride_h: stride,
+            ..Default::default()
+        };
+        self.add_layer(name, &[bottom], &[top], &params, &[])
+    }
+
+    /// Adds a fully connected layer of `num_output` values, `weights` being laid out as output
+    /// then input.
+    pub fn inner_product(
+        &mut self,
+        name: &str,
+        bottom: &str,
+        top: &str,
+        num_output: i32,
+        weights: &[f32],
+        bias: Option<&[f32]>,
+    ) -> Result<&mut Self> {
+        let params = InnerProductParams {
+            num_output,
+            bias_term: bias.is_some() as i32,
+            weight_data_size: weights.len() as i32,
+            ..Default::default()
+        };
+        let bias = bias.map(|bias| (WeightType::Float32, bias));
+        let weights: Vec<_> = [(WeightType::Auto, weights)]
+            .into_iter()
+            .chain(bias)
+            .collect();
+        self.add_layer(name, &[bottom], &[top], &params, &weights)
+    }
+
+    /// Adds a ReLU activation.
+    pub fn relu(&mut self, name: &str, bottom: &str, top: &str) -> Result<&mut Self> {
+        self.add_layer(name, &[bottom], &[top], &ReLUParams::default(), &[])
+    }
+
+    /// Returns the network built so far.
+    pub fn graph(&self) -> Graph {
+        let mut uses: HashMap<&str, usize> = HashMap::new();
+        for bottom in self.layers.iter().flat_map(|l| &l.bottoms) {
+            *uses.entry(bottom.as_str()).or_default() += 1;
+        }
+
+        let mut graph = Graph::default();
+        let mut blob_indices: HashMap<String, usize> = HashMap::new();
+        // Outputs of the split of each shared blob, and how many are consumed so far.
+        let mut split_tops: HashMap<&str, (Vec<String>, usize)> = HashMap::new();
+        let mut splits = 0;
+        for layer in &self.layers {
+            let bottoms = layer
+                .bottoms
+                .iter()
+                .map(|bottom| match split_tops.get_mut(bottom.as_str()) {
+                    Some((tops, used)) => {
+                        *used += 1;
+                        tops[*used - 1].clone()
+                    }
+                    None => bottom.clone(),
+                })
+                .collect();
+            push_layer(
+                &mut graph,
+                &mut blob_indices,
+                &layer.type_name,
+                &layer.name,
+                bottoms,
+                layer.tops.clone(),
+                layer.params.clone(),
+            );
+
+            for top in &layer.tops {
+                let count = uses.get(top.as_str()).copied().unwrap_or(0);
+                if count > 1 {
+                    let tops: Vec<_> = (0..count)
+                        .map(|i| self.unused_blob_name(format!("{}_splitncnn_{}", top, i)))
+                        .collect();
+                    push_layer(
+                        &mut graph,
+                        &mut blob_indices,
+                        "Split",
+                        &format!("splitncnn_{}", splits),
+                        vec![top.clone()],
+                        tops.clone(),
+                        ParamMap::new(),
+                    );
+                    split_tops.insert(top, (tops, 0));
+                    splits += 1;
+                }
+            }
+        }
+
+        graph.declared_layer_count = graph.layers.len();
+        graph.declared_blob_count = graph.blobs.len();
+        graph
+    }
+
+    /// Returns `name`, suffixed if needed to differ from the blobs added.
+    ///
+    /// Split outputs of different blobs cannot collide with each other, as a suffixed name ends
+    /// with a letter where they end with a digit.
+    fn unused_blob_name(&self, name: String) -> String {
+        let mut candidate = name.clone();
+        while self.blobs.contains(&candidate) {
+            candidate.push('x');
+        }
+        candidate
+    }
+
+    /// Returns the network built so far in the text `.param` format.
+    pub fn param_text(&self) -> String {
+        self.graph().to_string()
+    }
+
+    /// Returns the weights added so far in the `.bin` format.
+    pub fn weights(&self) -> &[u8] {
+        &self.weights
+    }
+
+    /// Writes the `.param` text and `.bin` weights files.
+    pub fn write(&self, param_path: impl AsRef<Path>, bin_path: impl AsRef<Path>) -> Result<()> {
+        let (param_path, bin_path) = (param_path.as_ref(), bin_path.as_ref());
+        std::fs::write(param_path, self.param_text()).map_err(|e| Error::io(param_path, e))?;
+        std::fs::write(bin_path, &self.weights).map_err(|e| Error::io(bin_path, e))
+    }
+
+    /// Returns a [NetBuilder] loading the network, to set options or custom layers before
+    /// building it.
+    pub fn net_builder(&self) -> Result<NetBuilder<'static>> {
+        let weights = std::io::Cursor::new(self.weights.clone());
+        Ok(NetBuilder::new()
+            .set_param_str(&self.param_text())?
+            .set_model_datareader(DataReader::from_reader(weights)))
+    }
+
+    /// Builds a live [Net] with default options.
+    pub fn build_net(&self) -> Result<Net<'static>> {
+        self.net_builder()?.build()
+    }
+}
+
+fn push_layer(
+    graph: &mut Graph,
+    blob_indices: &mut HashMap<String, usize>,
+    type_name: &str,
+    name: &str,
+    bottoms: Vec<String>,
+    tops: Vec<String>,
+    params: ParamMap,
+) {
+    let index = graph.layers.len();
+    let bottoms = bottoms
+        .into_iter()
+        .map(|bottom| {
+            let blob = blob_indices[&bottom];
+            graph.blobs[blob].consumers.push(index);
+            blob
+        })
+        .collect();
+    let tops = tops
+        .into_iter()
+        .map(|top| {
+            let blob = graph.blobs.len();
+            graph.blobs.push(Blob {
+                name: top.clone(),
+                producer: Some(index),
+                consumers: Vec::new(),
+            });
+            blob_indices.insert(top, blob);
+            blob
+        })
+        .collect();
+    graph.layers.push(Layer {
+        type_name: type_name.to_string(),
+        name: name.to_string(),
+        bottoms,
+        tops,
+        params,
+        line: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::ParamValue;
+
+    #[test]
+    fn split_shared_blobs() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .input("data", 4, 0, 0)
+            .unwrap()
+            .relu("relu", "data", "a")
+            .unwrap()
+            .layer(
+                "BinaryOp",
+                "add",
+                &["data", "a"],
+                &["output"],
+                ParamMap::new(),
+                &[],
+            )
+            .unwrap();
+        assert!(builder.relu("relu2", "missing", "b").is_err());
+        assert!(builder.relu("relu2", "a", "output").is_err());
+
+        let graph = builder.graph();
+        let types: Vec<_> = graph.layers.iter().map(|l| l.type_name.as_str()).collect();
+        assert_eq!(vec!["Input", "Split", "ReLU", "BinaryOp"], types);
+        let split = &graph.layers[1];
+        assert_eq!("splitncnn_0", split.name);
+        assert_eq!(2, split.tops.len());
+        assert_eq!(split.tops[1], graph.layers[3].bottoms[0]);
+        assert_eq!(split.tops[0], graph.layers[2].bottoms[0]);
+
+        let reparsed = Graph::parse(&builder.param_text()).unwrap();
+        assert_eq!(graph.layers.len(), reparsed.layers.len());
+        assert_eq!(graph.output_blobs(), reparsed.output_blobs());
+
+        // Split outputs avoid the names of blobs added.
+        builder.relu("relu3", "a", "a_splitncnn_0").unwrap();
+        let graph = builder.graph();
+        assert!(graph.validate_with(|_| true).is_empty());
+        assert_eq!("a_splitncnn_0x", graph.blobs[graph.layers[3].tops[0]].name);
+    }
+
+    #[test]
+    fn weights_layout() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .input("data", 2, 0, 0)
+            .unwrap()
+            .inner_product("fc", "data", "output", 1, &[1.0, 2.0], Some(&[0.5]))
+            .unwrap();
+        let expected: Vec<u8> = [0u8; 4]
+            .into_iter()
+            .chain([1.0f32, 2.0, 0.5].iter().flat_map(|v| v.to_le_bytes()))
+            .collect();
+        assert_eq!(expected, builder.weights());
+        assert_eq!(
+            Some(&ParamValue::Int(2)),
+            builder.graph().layers[1].params.get(2)
+        );
+    }
+
+    #[test]
+    fn build_net() {
+        let mut builder = GraphBuilder::new();
+        builder
+            .input("data", 4, 4, 1)
+            .unwrap()
+            .convolution(
+                "conv",
+                "data",
+                "conv",
+                &ConvolutionParams {
+                    num_output: 1,
+                    kernel_w: 3,
+                    kernel_h: 3,
+                    pad_left: 1,
+                    pad_right: 1,
+                    pad_top: 1,
+                    pad_bottom: 1,
+                    ..Default::default()
+                },
+                &[1.0; 9],
+                Some(&[-1.0]),
+            )
+            .unwrap()
+            .relu("relu", "conv", "relu")
+            .unwrap()
+            .pooling("pool", "relu", "output", PoolingType::Max, 2, 2)
+            .unwrap();
+
+        let mut net = builder.build_net().unwrap();
+        let mut input = crate::Mat::new_3d(4, 4, 1, None).unwrap();
+        input.fill(1.0);
+        let mut output = crate::Mat::new();
+        let mut ex = net.create_extractor();
+        ex.input("data", &input).unwrap();
+        ex.extract("output", &mut output).unwrap();
+        assert_eq!((2, 2), (output.width(), output.height()));
+        assert_eq!(&[8.0; 4], output.channel::<f32>(0).unwrap());
+    }
+}
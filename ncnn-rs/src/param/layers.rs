@@ -0,0 +1,493 @@
+//! Typed parameters of common ncnn layer types.
+
+use super::{Layer, ParamMap, ParamValue};
+use crate::error::{ensure, Error, Result};
+
+/// Parameters of an ncnn layer type, converted from and to the numeric ids of a [ParamMap].
+///
+/// Ids not covered by the typed fields are kept in an `extra` map, so that converting back and
+/// forth preserves them.
+pub trait LayerParams: Sized {
+    /// ncnn layer type, e.g. `Convolution`.
+    const TYPE_NAME: &'static str;
+
+    /// Reads the parameters, failing if a value has the wrong kind.
+    fn from_params(params: &ParamMap) -> Result<Self>;
+
+    /// Writes the parameters, omitting values equal to their default.
+    fn to_params(&self) -> ParamMap;
+}
+
+/// Value type of a typed parameter.
+trait ParamField: Sized {
+    /// Whether the values are floats.
+    const FLOAT: bool;
+
+    fn read(params: &ParamMap, id: i32, default: Self) -> Result<Self>;
+    fn to_value(&self) -> ParamValue;
+}
+
+fn kind_error(id: i32, expected: &str) -> Error {
+    Error::param(format!("Param {} must be {}", id, expected))
+}
+
+impl ParamField for i32 {
+    const FLOAT: bool = false;
+
+    fn read(params: &ParamMap, id: i32, default: Self) -> Result<Self> {
+        ensure!(
+            !params.get(id).is_some_and(ParamValue::is_array),
+            kind_error(id, "an integer")
+        );
+        Ok(params.get_int(id, default))
+    }
+
+    fn to_value(&self) -> ParamValue {
+        ParamValue::Int(*self)
+    }
+}
+
+impl ParamField for f32 {
+    const FLOAT: bool = true;
+
+    fn read(params: &ParamMap, id: i32, default: Self) -> Result<Self> {
+        ensure!(
+            !params.get(id).is_some_and(ParamValue::is_array),
+            kind_error(id, "a float")
+        );
+        Ok(params.get_float(id, default))
+    }
+
+    fn to_value(&self) -> ParamValue {
+        ParamValue::Float(*self)
+    }
+}
+
+impl ParamField for Vec<i32> {
+    const FLOAT: bool = false;
+
+    fn read(params: &ParamMap, id: i32, default: Self) -> Result<Self> {
+        match params.get(id) {
+            None => Ok(default),
+            Some(_) => params
+                .get_int_array(id)
+                .ok_or_else(|| kind_error(id, "an integer array")),
+        }
+    }
+
+    fn to_value(&self) -> ParamValue {
+        ParamValue::IntArray(self.clone())
+    }
+}
+
+impl ParamField for Vec<f32> {
+    const FLOAT: bool = true;
+
+    fn read(params: &ParamMap, id: i32, default: Self) -> Result<Self> {
+        match params.get(id) {
+            None => Ok(default),
+            Some(_) => params
+                .get_float_array(id)
+                .ok_or_else(|| kind_error(id, "a float array")),
+        }
+    }
+
+    fn to_value(&self) -> ParamValue {
+        ParamValue::FloatArray(self.clone())
+    }
+}
+
+/// Defines typed parameter structs, each field with its type, default and id, arrays using the
+/// `-23300 - id` keys of [ParamMap]. Defaults may refer to fields defined before, as ncnn does
+/// e.g. for `kernel_h` defaulting to `kernel_w`.
+macro_rules! layer_params {
+    ($(
+        $(#[$doc:meta])*
+        $name:ident($type_name:literal) {
+            $($(#[$field_doc:meta])* $field:ident: $ty:ty = $default:expr => $id:literal,)*
+        }
+    )*) => {
+        $(
+            $(#[$doc])*
+            #[derive(Clone, Debug, PartialEq)]
+            pub struct $name {
+                $($(#[$field_doc])* pub $field: $ty,)*
+                /// Parameters without a typed field, e.g. shape hints.
+                pub extra: ParamMap,
+            }
+
+            impl LayerParams for $name {
+                const TYPE_NAME: &'static str = $type_name;
+
+                fn from_params(params: &ParamMap) -> Result<Self> {
+                    let mut extra = params.clone();
+                    $(
+                        let $field = <$ty as ParamField>::read(params, $id, $default)?;
+                        extra.remove($id);
+                    )*
+                    Ok(Self { $($field,)* extra })
+                }
+
+                fn to_params(&self) -> ParamMap {
+                    let mut params = self.extra.clone();
+                    $(let $field = self.$field.clone();)*
+                    $(
+                        if $field != $default {
+                            params.set($id, $field.to_value());
+                        }
+                    )*
+                    params
+                }
+            }
+
+            impl Default for $name {
+                fn default() -> Self {
+                    $(let $field: $ty = $default;)*
+                    Self { $($field,)* extra: ParamMap::new() }
+                }
+            }
+        )*
+
+        /// Whether param `id` of `type_name` layers holds floats, `None` for ids without a
+        /// typed field.
+        pub(crate) fn is_float_param(type_name: &str, id: i32) -> Option<bool> {
+            match type_name {
+                $($type_name => match id {
+                    $($id => Some(<$ty as ParamField>::FLOAT),)*
+                    _ => None,
+                },)*
+                _ => None,
+            }
+        }
+    };
+}
+
+layer_params! {
+    /// Parameters of `Input`, 0 marking unused dimensions.
+    InputParams("Input") {
+        w: i32 = 0 => 0,
+        h: i32 = 0 => 1,
+        d: i32 = 0 => 11,
+        c: i32 = 0 => 2,
+    }
+
+    /// Parameters of `Convolution`.
+    ConvolutionParams("Convolution") {
+        num_output: i32 = 0 => 0,
+        kernel_w: i32 = 0 => 1,
+        kernel_h: i32 = kernel_w => 11,
+        dilation_w: i32 = 1 => 2,
+        dilation_h: i32 = dilation_w => 12,
+        stride_w: i32 = 1 => 3,
+        stride_h: i32 = stride_w => 13,
+        /// Left padding, -233 pads as `SAME_UPPER` and -234 as `SAME_LOWER`.
+        pad_left: i32 = 0 => 4,
+        pad_right: i32 = pad_left => 15,
+        pad_top: i32 = pad_left => 14,
+        pad_bottom: i32 = pad_top => 16,
+        pad_value: f32 = 0.0 => 18,
+        bias_term: i32 = 0 => 5,
+        weight_data_size: i32 = 0 => 6,
+        int8_scale_term: i32 = 0 => 8,
+        /// Fused activation: 0 none, 1 ReLU, 2 leaky ReLU, 3 clip, 4 sigmoid, 5 mish, 6 hardswish.
+        activation_type: i32 = 0 => 9,
+        activation_params: Vec<f32> = Vec::new() => -23310,
+        dynamic_weight: i32 = 0 => 19,
+    }
+
+    /// Parameters of `ConvolutionDepthWise`, fields as in [ConvolutionParams].
+    ConvolutionDepthWiseParams("ConvolutionDepthWise") {
+        num_output: i32 = 0 => 0,
+        kernel_w: i32 = 0 => 1,
+        kernel_h: i32 = kernel_w => 11,
+        dilation_w: i32 = 1 => 2,
+        dilation_h: i32 = dilation_w => 12,
+        stride_w: i32 = 1 => 3,
+        stride_h: i32 = stride_w => 13,
+        pad_left: i32 = 0 => 4,
+        pad_right: i32 = pad_left => 15,
+        pad_top: i32 = pad_left => 14,
+        pad_bottom: i32 = pad_top => 16,
+        pad_value: f32 = 0.0 => 18,
+        bias_term: i32 = 0 => 5,
+        weight_data_size: i32 = 0 => 6,
+        group: i32 = 1 => 7,
+        int8_scale_term: i32 = 0 => 8,
+        activation_type: i32 = 0 => 9,
+        activation_params: Vec<f32> = Vec::new() => -23310,
+        dynamic_weight: i32 = 0 => 19,
+    }
+
+    /// Parameters of `Deconvolution`.
+    DeconvolutionParams("Deconvolution") {
+        num_output: i32 = 0 => 0,
+        kernel_w: i32 = 0 => 1,
+        kernel_h: i32 = kernel_w => 11,
+        dilation_w: i32 = 1 => 2,
+        dilation_h: i32 = dilation_w => 12,
+        stride_w: i32 = 1 => 3,
+        stride_h: i32 = stride_w => 13,
+        /// Left padding, -233 pads as `SAME_UPPER` and -234 as `SAME_LOWER`.
+        pad_left: i32 = 0 => 4,
+        pad_right: i32 = pad_left => 15,
+        pad_top: i32 = pad_left => 14,
+        pad_bottom: i32 = pad_top => 16,
+        output_pad_right: i32 = 0 => 18,
+        output_pad_bottom: i32 = output_pad_right => 19,
+        /// Output size, used when there is no padding.
+        output_w: i32 = 0 => 20,
+        output_h: i32 = output_w => 21,
+        bias_term: i32 = 0 => 5,
+        weight_data_size: i32 = 0 => 6,
+        /// Fused activation, as in [ConvolutionParams::activation_type].
+        activation_type: i32 = 0 => 9,
+        activation_params: Vec<f32> = Vec::new() => -23310,
+        dynamic_weight: i32 = 0 => 28,
+    }
+
+    /// Parameters of `DeconvolutionDepthWise`, fields as in [DeconvolutionParams].
+    DeconvolutionDepthWiseParams("DeconvolutionDepthWise") {
+        num_output: i32 = 0 => 0,
+        kernel_w: i32 = 0 => 1,
+        kernel_h: i32 = kernel_w => 11,
+        dilation_w: i32 = 1 => 2,
+        dilation_h: i32 = dilation_w => 12,
+        stride_w: i32 = 1 => 3,
+        stride_h: i32 = stride_w => 13,
+        pad_left: i32 = 0 => 4,
+        pad_right: i32 = pad_left => 15,
+        pad_top: i32 = pad_left => 14,
+        pad_bottom: i32 = pad_top => 16,
+        output_pad_right: i32 = 0 => 18,
+        output_pad_bottom: i32 = output_pad_right => 19,
+        output_w: i32 = 0 => 20,
+        output_h: i32 = output_w => 21,
+        bias_term: i32 = 0 => 5,
+        weight_data_size: i32 = 0 => 6,
+        group: i32 = 1 => 7,
+        activation_type: i32 = 0 => 9,
+        activation_params: Vec<f32> = Vec::new() => -23310,
+        dynamic_weight: i32 = 0 => 28,
+    }
+
+    /// Parameters of `Pooling`.
+    PoolingParams("Pooling") {
+        /// 0 for max pooling, 1 for average pooling.
+        pooling_type: i32 = 0 => 0,
+        kernel_w: i32 = 0 => 1,
+        kernel_h: i32 = kernel_w => 11,
+        stride_w: i32 = 1 => 2,
+        stride_h: i32 = stride_w => 12,
+        pad_left: i32 = 0 => 3,
+        pad_right: i32 = pad_left => 14,
+        pad_top: i32 = pad_left => 13,
+        pad_bottom: i32 = pad_top => 15,
+        global_pooling: i32 = 0 => 4,
+        /// 0 full padding, 1 valid padding, 2 `SAME_UPPER` and 3 `SAME_LOWER`.
+        pad_mode: i32 = 0 => 5,
+        avgpool_count_include_pad: i32 = 0 => 6,
+        adaptive_pooling: i32 = 0 => 7,
+        out_w: i32 = 0 => 8,
+        out_h: i32 = out_w => 18,
+    }
+
+    /// Parameters of `InnerProduct`.
+    InnerProductParams("InnerProduct") {
+        num_output: i32 = 0 => 0,
+        bias_term: i32 = 0 => 1,
+        weight_data_size: i32 = 0 => 2,
+        int8_scale_term: i32 = 0 => 8,
+        /// Fused activation, as in [ConvolutionParams::activation_type].
+        activation_type: i32 = 0 => 9,
+        activation_params: Vec<f32> = Vec::new() => -23310,
+    }
+
+    /// Parameters of `ReLU`, leaky when `slope` is not 0.
+    ReLUParams("ReLU") {
+        slope: f32 = 0.0 => 0,
+    }
+
+    /// Parameters of `Clip`.
+    ClipParams("Clip") {
+        min: f32 = -f32::MAX => 0,
+        max: f32 = f32::MAX => 1,
+    }
+
+    /// Parameters of `Softmax`.
+    SoftmaxParams("Softmax") {
+        axis: i32 = 0 => 0,
+        /// Set by converters to get the expected axis for 3D blobs.
+        fixbug0: i32 = 0 => 1,
+    }
+
+    /// Parameters of `Concat`.
+    ConcatParams("Concat") {
+        axis: i32 = 0 => 0,
+    }
+
+    /// Parameters of `Slice`, -233 in `slices` taking the remaining size.
+    SliceParams("Slice") {
+        slices: Vec<i32> = Vec::new() => -23300,
+        axis: i32 = 0 => 1,
+    }
+
+    /// Parameters of `Eltwise`.
+    EltwiseParams("Eltwise") {
+        /// 0 for product, 1 for sum and 2 for max.
+        op_type: i32 = 0 => 0,
+        coeffs: Vec<f32> = Vec::new() => -23301,
+    }
+
+    /// Parameters of `BinaryOp`.
+    BinaryOpParams("BinaryOp") {
+        /// 0 add, 1 sub, 2 mul, 3 div, 4 max, 5 min, 6 pow, 7 rsub, 8 rdiv, 9 rpow, 10 atan2
+        /// and 11 ratan2.
+        op_type: i32 = 0 => 0,
+        /// Uses `b` as second operand instead of a second blob.
+        with_scalar: i32 = 0 => 1,
+        b: f32 = 0.0 => 2,
+    }
+
+    /// Parameters of `Reshape`, -233 keeping a dimension unset and 0 copying the input one.
+    ReshapeParams("Reshape") {
+        w: i32 = -233 => 0,
+        h: i32 = -233 => 1,
+        d: i32 = -233 => 11,
+        c: i32 = -233 => 2,
+        permute: i32 = 0 => 3,
+    }
+
+    /// Parameters of `Interp`.
+    InterpParams("Interp") {
+        /// 1 nearest, 2 bilinear and 3 bicubic.
+        resize_type: i32 = 0 => 0,
+        height_scale: f32 = 1.0 => 1,
+        width_scale: f32 = 1.0 => 2,
+        output_height: i32 = 0 => 3,
+        output_width: i32 = 0 => 4,
+        dynamic_target_size: i32 = 0 => 5,
+        align_corner: i32 = 0 => 6,
+    }
+
+    /// Parameters of `Padding`.
+    PaddingParams("Padding") {
+        top: i32 = 0 => 0,
+        bottom: i32 = 0 => 1,
+        left: i32 = 0 => 2,
+        right: i32 = 0 => 3,
+        /// 0 constant, 1 replicate and 2 reflect.
+        pad_type: i32 = 0 => 4,
+        value: f32 = 0.0 => 5,
+        per_channel_pad_data_size: i32 = 0 => 6,
+        front: i32 = 0 => 7,
+        behind: i32 = front => 8,
+    }
+
+    /// Parameters of `BatchNorm`.
+    BatchNormParams("BatchNorm") {
+        channels: i32 = 0 => 0,
+        eps: f32 = 0.0 => 1,
+    }
+
+    /// Parameters of `Scale`, -233 in `scale_data_size` taking the scale from a second blob.
+    ScaleParams("Scale") {
+        scale_data_size: i32 = 0 => 0,
+        bias_term: i32 = 0 => 1,
+    }
+}
+
+impl Layer {
+    /// Reads the layer parameters as `P`, failing if the layer is of another type.
+    pub fn typed_params<P: LayerParams>(&self) -> Result<P> {
+        ensure!(
+            self.type_name == P::TYPE_NAME,
+            Error::InvalidArgument(format!(
+                "Layer `{}` is a {}, not a {}",
+                self.name,
+                self.type_name,
+                P::TYPE_NAME
+            ))
+        );
+        P::from_params(&self.params).map_err(|e| match self.line {
+            Some(line) => e.with_line(line),
+            None => e,
+        })
+    }
+
+    /// Replaces the layer parameters, failing if the layer is of another type.
+    pub fn set_typed_params<P: LayerParams>(&mut self, params: &P) -> Result<()> {
+        ensure!(
+            self.type_name == P::TYPE_NAME,
+            Error::InvalidArgument(format!(
+                "Layer `{}` is a {}, not a {}",
+                self.name,
+                self.type_name,
+                P::TYPE_NAME
+            ))
+        );
+        self.params = params.to_params();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::Graph;
+
+    #[test]
+    fn convolution_round_trip() {
+        let graph = Graph::parse(
+            "7767517\n2 2\nInput data 0 1 data 0=224 1=224 2=3\n\
+             Convolution conv1 1 1 data conv1 0=32 1=3 3=2 4=1 5=1 6=864 9=1 -23330=4,3,112,112,32\n",
+        )
+        .unwrap();
+        let conv: ConvolutionParams = graph.layers[1].typed_params().unwrap();
+        assert_eq!(32, conv.num_output);
+        assert_eq!((3, 3), (conv.kernel_w, conv.kernel_h));
+        assert_eq!((2, 2), (conv.stride_w, conv.stride_h));
+        assert_eq!(
+            (1, 1, 1, 1),
+            (conv.pad_left, conv.pad_right, conv.pad_top, conv.pad_bottom)
+        );
+        assert_eq!(1, conv.dilation_h);
+        assert_eq!(1, conv.activation_type);
+        assert!(conv.extra.contains(-23330));
+        assert_eq!(graph.layers[1].params, conv.to_params());
+
+        let input: InputParams = graph.layers[0].typed_params().unwrap();
+        assert_eq!((224, 224, 0, 3), (input.w, input.h, input.d, input.c));
+        assert!(graph.layers[0].typed_params::<PoolingParams>().is_err());
+    }
+
+    #[test]
+    fn edit_params() {
+        let mut graph = Graph::parse(
+            "7767517\n2 2\nInput data 0 1 data\nPooling pool 1 1 data pool 0=1 1=2 2=2 4=1\n",
+        )
+        .unwrap();
+        let mut pool: PoolingParams = graph.layers[1].typed_params().unwrap();
+        assert_eq!(1, pool.global_pooling);
+        pool.global_pooling = 0;
+        pool.kernel_h = 3;
+        graph.layers[1].set_typed_params(&pool).unwrap();
+        assert!(graph.to_string().contains(" 0=1 1=2 2=2 11=3\n"));
+        assert!(graph.layers[0].set_typed_params(&pool).is_err());
+
+        assert_eq!(-f32::MAX, ClipParams::default().min);
+        assert!(ReLUParams::default().to_params().is_empty());
+    }
+
+    #[test]
+    fn wrong_kind() {
+        let graph =
+            Graph::parse("7767517\n1 2\nSlice slice 1 2 data a b -23300=2,80,-233\n").unwrap();
+        let slice: SliceParams = graph.layers[0].typed_params().unwrap();
+        assert_eq!(vec![80, -233], slice.slices);
+
+        let mut params = ParamMap::new();
+        params.set(1, ParamValue::IntArray(vec![0]));
+        let err = SliceParams::from_params(&params).unwrap_err();
+        assert_eq!("Param 1 must be an integer", err.to_string());
+    }
+}
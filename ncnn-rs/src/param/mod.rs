@@ -0,0 +1,439 @@
+//! Pure-Rust model of ncnn `.param` network descriptions.
+//!
+//! A [Graph] can be parsed from the text format accepted by [crate::NetBuilder::set_param_path]
+//! or the binary format accepted by [crate::NetBuilder::set_param_bin_path], and inspected
+//! without creating an ncnn network. A [GraphBuilder] builds networks in code instead, and
+//! [LayerParams] types give the parameters of common layer types a name. [Graph::infer_shapes]
+//! computes blob shapes without running the network, [Graph::model_stats] estimates its cost
+//! and [Graph::validate] reports structural errors with their line.
+
+mod bin;
+mod builder;
+mod layers;
+mod shape;
+mod stats;
+mod text;
+mod types;
+mod validate;
+mod weights;
+
+pub use builder::*;
+pub use layers::*;
+pub use shape::*;
+pub use stats::*;
+pub use types::*;
+pub use validate::*;
+
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Magic number on the first line of every text `.param` file.
+pub const MAGIC: i32 = 7767517;
+
+/// Parameter ids at or below this value describe arrays, the array id being `-(id + 23300)`.
+pub const ARRAY_ID_OFFSET: i32 = -23300;
+
+/// Single value of a layer parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamValue {
+    Int(i32),
+    Float(f32),
+    IntArray(Vec<i32>),
+    FloatArray(Vec<f32>),
+    /// Bits of a binary param of unknown type, read as an integer or the bits of a float and
+    /// written as an integer.
+    Raw(i32),
+    /// Bits of a binary array param of unknown type, as [ParamValue::Raw].
+    RawArray(Vec<i32>),
+}
+
+impl ParamValue {
+    /// Returns `true` for array values.
+    pub fn is_array(&self) -> bool {
+        matches!(
+            self,
+            ParamValue::IntArray(_) | ParamValue::FloatArray(_) | ParamValue::RawArray(_)
+        )
+    }
+}
+
+/// Layer parameters keyed by their numeric id.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParamMap {
+    values: BTreeMap<i32, ParamValue>,
+}
+
+impl ParamMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: i32) -> Option<&ParamValue> {
+        self.values.get(&id)
+    }
+
+    /// Returns an integer parameter, converting floats, or `default` when missing.
+    pub fn get_int(&self, id: i32, default: i32) -> i32 {
+        match self.values.get(&id) {
+            Some(ParamValue::Int(v)) | Some(ParamValue::Raw(v)) => *v,
+            Some(ParamValue::Float(v)) => *v as i32,
+            _ => default,
+        }
+    }
+
+    /// Returns a float parameter, converting integers, or `default` when missing.
+    pub fn get_float(&self, id: i32, default: f32) -> f32 {
+        match self.values.get(&id) {
+            Some(ParamValue::Int(v)) => *v as f32,
+            Some(ParamValue::Float(v)) => *v,
+            Some(ParamValue::Raw(v)) => f32::from_bits(*v as u32),
+            _ => default,
+        }
+    }
+
+    /// Returns an array parameter as integers, converting floats.
+    pub fn get_int_array(&self, id: i32) -> Option<Vec<i32>> {
+        match self.values.get(&id) {
+            Some(ParamValue::IntArray(v)) | Some(ParamValue::RawArray(v)) => Some(v.clone()),
+            Some(ParamValue::FloatArray(v)) => Some(v.iter().map(|v| *v as i32).collect()),
+            _ => None,
+        }
+    }
+
+    /// Returns an array parameter as floats, converting integers.
+    pub fn get_float_array(&self, id: i32) -> Option<Vec<f32>> {
+        match self.values.get(&id) {
+            Some(ParamValue::IntArray(v)) => Some(v.iter().map(|v| *v as f32).collect()),
+            Some(ParamValue::FloatArray(v)) => Some(v.clone()),
+            Some(ParamValue::RawArray(v)) => {
+                Some(v.iter().map(|v| f32::from_bits(*v as u32)).collect())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, id: i32, value: ParamValue) {
+        self.values.insert(id, value);
+    }
+
+    pub fn remove(&mut self, id: i32) -> Option<ParamValue> {
+        self.values.remove(&id)
+    }
+
+    pub fn contains(&self, id: i32) -> bool {
+        self.values.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Iterates over parameters in ascending id order.
+    pub fn iter(&self) -> impl Iterator<Item = (i32, &ParamValue)> {
+        self.values.iter().map(|(id, v)| (*id, v))
+    }
+}
+
+/// Layer of a parsed network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    /// Layer type, e.g. `Convolution`.
+    pub type_name: String,
+    pub name: String,
+    /// Indices into [Graph::blobs] consumed by the layer.
+    pub bottoms: Vec<usize>,
+    /// Indices into [Graph::blobs] produced by the layer.
+    pub tops: Vec<usize>,
+    pub params: ParamMap,
+    /// 1-based line of the layer in the source text, if parsed from one.
+    pub line: Option<usize>,
+}
+
+/// Blob connecting layers of a parsed network.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Blob {
+    pub name: String,
+    /// Index of the producing layer, `None` if the blob is only consumed.
+    pub producer: Option<usize>,
+    /// Indices of the consuming layers.
+    pub consumers: Vec<usize>,
+}
+
+/// Network description made of layers connected by blobs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Graph {
+    pub layers: Vec<Layer>,
+    pub blobs: Vec<Blob>,
+    /// Layer count declared in the source header.
+    pub declared_layer_count: usize,
+    /// Blob count declared in the source header.
+    pub declared_blob_count: usize,
+    /// 1-based lines of text params past the declared layer count, which ncnn ignores.
+    pub surplus_lines: Vec<usize>,
+}
+
+impl Graph {
+    /// Parses a text `.param` description.
+    pub fn parse(text: &str) -> Result<Self> {
+        text::parse(text)
+    }
+
+    /// Reads and parses a text `.param` file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        Self::parse(&text)
+    }
+
+    /// Parses a binary `.param.bin` description.
+    ///
+    /// Layer and blob names are not part of the binary format, layers are named `{type}_{index}`
+    /// and blobs after their index.
+    pub fn from_bin(data: &[u8]) -> Result<Self> {
+        bin::read(data, &[]).map(|(graph, _)| graph)
+    }
+
+    /// Parses a binary `.param.bin` description which may use the custom layer types registered
+    /// in order as `custom_types`, returning the number of bytes the description takes.
+    pub(crate) fn from_bin_prefix(data: &[u8], custom_types: &[&str]) -> Result<(Self, usize)> {
+        bin::read(data, custom_types)
+    }
+
+    /// Reads and parses a binary `.param.bin` file.
+    pub fn from_bin_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        Self::from_bin(&data)
+    }
+
+    /// Serializes the graph in the binary `.param.bin` format, dropping layer and blob names.
+    ///
+    /// Fails for layer types which are not built into ncnn.
+    pub fn to_bin(&self) -> Result<Vec<u8>> {
+        bin::write(self)
+    }
+
+    /// Generates Rust source of a module with layer and blob index constants, the Rust
+    /// counterpart of the `*.id.h` header written by ncnn2mem.
+    ///
+    /// Blob indices can be used to address blobs of a network loaded from the binary format.
+    pub fn id_table(&self, module: &str) -> String {
+        bin::id_table(self, module)
+    }
+
+    /// Returns the index of the first blob with the given name.
+    pub fn blob_index(&self, name: &str) -> Option<usize> {
+        self.blobs.iter().position(|b| b.name == name)
+    }
+
+    /// Returns the index of the first layer with the given name.
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    /// Returns the blobs produced by `Input` layers.
+    pub fn input_blobs(&self) -> Vec<usize> {
+        self.layers
+            .iter()
+            .filter(|l| l.type_name == "Input")
+            .flat_map(|l| l.tops.iter().copied())
+            .collect()
+    }
+
+    /// Returns the produced blobs which no layer consumes.
+    pub fn output_blobs(&self) -> Vec<usize> {
+        self.blobs
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.producer.is_some() && b.consumers.is_empty())
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+impl FromStr for Graph {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Graph {
+    /// Writes the graph in the text `.param` format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        text::write(self, f)
+    }
+}
+
+/// Directory of the `.param` files shipped with the crate.
+#[cfg(test)]
+pub(crate) const PARAMS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../params");
+
+/// Paths of the `.param` files in [PARAMS_DIR], sorted.
+#[cfg(test)]
+pub(crate) fn shipped_params() -> impl Iterator<Item = std::path::PathBuf> {
+    let mut paths: Vec<_> = std::fs::read_dir(PARAMS_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "param"))
+        .collect();
+    paths.sort();
+    paths.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_all_params() {
+        let mut count = 0;
+        for path in shipped_params() {
+            let graph = Graph::from_path(&path).unwrap();
+            assert_eq!(graph.declared_layer_count, graph.layers.len(), "{:?}", path);
+            assert_eq!(graph.declared_blob_count, graph.blobs.len(), "{:?}", path);
+            if path.ends_with("nanodet_m.param") {
+                // The shipped header leaves out a last layer, which ncnn does not load.
+                assert_eq!(vec![182], graph.surplus_lines);
+                assert_eq!(None, graph.blob_index("output"));
+            } else {
+                assert!(graph.surplus_lines.is_empty(), "{:?}", path);
+            }
+            assert!(!graph.input_blobs().is_empty(), "{:?}", path);
+
+            let reparsed = Graph::parse(&graph.to_string()).unwrap();
+            assert_eq!(graph.layers.len(), reparsed.layers.len());
+            for (a, b) in graph.layers.iter().zip(&reparsed.layers) {
+                assert_eq!(a.name, b.name);
+                assert_eq!(a.bottoms, b.bottoms);
+                assert_eq!(a.tops, b.tops);
+                assert_eq!(a.params, b.params);
+            }
+            count += 1;
+        }
+        assert_eq!(33, count);
+    }
+
+    #[test]
+    fn bin_round_trip() {
+        for path in shipped_params() {
+            let graph = Graph::from_path(&path).unwrap();
+            let bin = graph.to_bin().unwrap();
+            let decoded = Graph::from_bin(&bin).unwrap();
+            assert_eq!(graph.layers.len(), decoded.layers.len(), "{:?}", path);
+            assert_eq!(graph.blobs.len(), decoded.blobs.len(), "{:?}", path);
+            for (a, b) in graph.layers.iter().zip(&decoded.layers) {
+                assert_eq!(a.type_name, b.type_name);
+                assert_eq!(a.bottoms, b.bottoms);
+                assert_eq!(a.tops, b.tops);
+            }
+            assert_eq!(bin, decoded.to_bin().unwrap(), "{:?}", path);
+        }
+    }
+
+    /// 32-bit words a value is stored as in the binary format.
+    fn words(value: &ParamValue) -> Vec<i32> {
+        match value {
+            ParamValue::Int(v) | ParamValue::Raw(v) => vec![*v],
+            ParamValue::Float(v) => vec![v.to_bits() as i32],
+            ParamValue::IntArray(v) | ParamValue::RawArray(v) => v.clone(),
+            ParamValue::FloatArray(v) => v.iter().map(|v| v.to_bits() as i32).collect(),
+        }
+    }
+
+    #[test]
+    fn bin_text_round_trip() {
+        for path in shipped_params() {
+            let graph = Graph::from_path(&path).unwrap();
+            let decoded = Graph::from_bin(&graph.to_bin().unwrap()).unwrap();
+            let reparsed = Graph::parse(&decoded.to_string()).unwrap();
+            for (a, b) in graph.layers.iter().zip(&reparsed.layers) {
+                assert_eq!(a.params.len(), b.params.len(), "{:?} {}", path, a.name);
+                for (id, value) in a.params.iter() {
+                    let kind = layers::is_float_param(&a.type_name, id);
+                    let decoded = b.params.get(id).unwrap();
+                    assert_eq!(words(value), words(decoded), "{:?} {} {}", path, a.name, id);
+                    if let Some(float) = kind {
+                        let is_float =
+                            matches!(decoded, ParamValue::Float(_) | ParamValue::FloatArray(_));
+                        assert_eq!(float, is_float, "{:?} {} {}", path, a.name, id);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bin_param_types() {
+        let graph = Graph::from_path(Path::new(PARAMS_DIR).join("yolov4-tiny.param")).unwrap();
+        let decoded = Graph::from_bin(&graph.to_bin().unwrap()).unwrap();
+
+        let conv = &decoded.layers[1];
+        assert_eq!("Convolution_1", conv.name);
+        assert_eq!(Some(&ParamValue::Int(864)), conv.params.get(6));
+        assert_eq!(
+            Some(&ParamValue::FloatArray(vec![0.1])),
+            conv.params.get(-23310)
+        );
+
+        // Values out of the usual float range keep their type, unknown ids their bits.
+        let graph = Graph::parse(
+            "7767517\n3 3\nInput data 0 1 data\nClip clip 1 1 data clip 0=0.0 1=3.402823e+38\n\
+             LayerNorm norm 1 1 clip norm 0=4 1=1e-12 2=1\n",
+        )
+        .unwrap();
+        let decoded = Graph::from_bin(&graph.to_bin().unwrap()).unwrap();
+        let clip = &decoded.layers[1].params;
+        assert_eq!(Some(&ParamValue::Float(0.0)), clip.get(0));
+        assert_eq!(3.402823e38, clip.get_float(1, 0.0));
+        let norm = &decoded.layers[2].params;
+        assert_eq!(Some(&ParamValue::Raw(4)), norm.get(0));
+        assert_eq!(1e-12, norm.get_float(1, 0.0));
+        assert!(decoded.to_string().contains(" 0=4 1=730643660 2=1\n"));
+    }
+
+    #[test]
+    fn id_table() {
+        let graph = Graph::from_path(Path::new(PARAMS_DIR).join("mobilenet.param")).unwrap();
+        let table = graph.id_table("mobilenet_param_id");
+        assert!(table.contains("pub mod mobilenet_param_id {"));
+        assert!(table.contains("    pub const LAYER_CONV1: usize = 1;"));
+        assert!(table.contains("    pub const BLOB_DATA: usize = 0;"));
+        assert!(table.contains("    pub const BLOB_CONV1_RELU1: usize = 1;"));
+    }
+
+    #[test]
+    fn mobilenet_structure() {
+        let graph = Graph::from_path(Path::new(PARAMS_DIR).join("mobilenet.param")).unwrap();
+        assert_eq!(31, graph.layers.len());
+
+        let conv = &graph.layers[1];
+        assert_eq!("Convolution", conv.type_name);
+        assert_eq!("conv1", conv.name);
+        assert_eq!(Some(4), conv.line);
+        assert_eq!(32, conv.params.get_int(0, 0));
+        assert_eq!(864, conv.params.get_int(6, 0));
+        assert_eq!(1, conv.params.get_int(2, 1));
+        assert_eq!(
+            Some(&ParamValue::IntArray(vec![3, 112, 112, 32])),
+            conv.params.get(-23330)
+        );
+
+        let data = graph.blob_index("data").unwrap();
+        assert_eq!(vec![data], graph.input_blobs());
+        assert_eq!(Some(0), graph.blobs[data].producer);
+        assert_eq!(vec![1], graph.blobs[data].consumers);
+        assert_eq!(
+            vec![graph.blob_index("output").unwrap()],
+            graph.output_blobs()
+        );
+    }
+}
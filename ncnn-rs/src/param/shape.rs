@@ -0,0 +1,875 @@
+//! Static shape inference over parsed graphs.
+
+use super::{
+    ConcatParams, ConvolutionDepthWiseParams, DeconvolutionParams, Graph, InnerProductParams,
+    InputParams, InterpParams, Layer, LayerParams, PaddingParams, PoolingParams, ReshapeParams,
+    SliceParams,
+};
+use crate::allocator::Allocator;
+use crate::error::{Error, Result};
+use crate::mat::Mat;
+use std::fmt;
+
+/// Parameter id of the shape hints written by ncnn's tools, 4 values per top blob.
+pub const SHAPE_HINT_ID: i32 = -23330;
+
+/// Reported for blobs with a depth, which [Shape] cannot represent.
+const UNSUPPORTED_4D: &str = "4D blobs are not supported";
+
+/// Shape of a blob, following ncnn's conventions of 1D blobs having a height and 2D and 1D
+/// blobs a channel count of 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Shape {
+    pub dims: i32,
+    pub w: i32,
+    pub h: i32,
+    pub c: i32,
+}
+
+impl Shape {
+    pub fn new_1d(w: i32) -> Self {
+        Self {
+            dims: 1,
+            w,
+            h: 1,
+            c: 1,
+        }
+    }
+
+    pub fn new_2d(w: i32, h: i32) -> Self {
+        Self {
+            dims: 2,
+            w,
+            h,
+            c: 1,
+        }
+    }
+
+    pub fn new_3d(w: i32, h: i32, c: i32) -> Self {
+        Self { dims: 3, w, h, c }
+    }
+
+    /// Shape of an existing matrix.
+    pub fn of(mat: &Mat) -> Self {
+        Self {
+            dims: mat.dimensions() as i32,
+            w: mat.width() as i32,
+            h: mat.height() as i32,
+            c: mat.channels() as i32,
+        }
+    }
+
+    /// Shape declared by `w`, `h` and `c` parameters, 0 marking unused dimensions.
+    fn declared(w: i32, h: i32, c: i32) -> Option<Self> {
+        if c > 0 {
+            Some(Self::new_3d(w, h, c))
+        } else if h > 0 {
+            Some(Self::new_2d(w, h))
+        } else if w > 0 {
+            Some(Self::new_1d(w))
+        } else {
+            None
+        }
+    }
+
+    /// Number of elements.
+    pub fn size(&self) -> i64 {
+        (self.w as i64)
+            .saturating_mul(self.h as i64)
+            .saturating_mul(self.c as i64)
+    }
+
+    /// Allocates a matrix of the shape, e.g. to extract a blob into.
+    pub fn new_mat(&self, alloc: Option<&Allocator>) -> Result<Mat> {
+        let invalid = || Error::InvalidDimension(format!("Cannot allocate a {} matrix", self));
+        let w = u32::try_from(self.w).map_err(|_| invalid())?;
+        let h = u32::try_from(self.h).map_err(|_| invalid())?;
+        let c = u32::try_from(self.c).map_err(|_| invalid())?;
+        match self.dims {
+            1 => Mat::new_1d(w, alloc),
+            2 => Mat::new_2d(w, h, alloc),
+            3 => Mat::new_3d(w, h, c, alloc),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Sizes from the outermost to the innermost axis, as indexed by ncnn's `axis` params.
+    fn axes(&self) -> Vec<i32> {
+        match self.dims {
+            1 => vec![self.w],
+            2 => vec![self.h, self.w],
+            _ => vec![self.c, self.h, self.w],
+        }
+    }
+
+    fn from_axes(axes: &[i32]) -> Self {
+        match *axes {
+            [w] => Self::new_1d(w),
+            [h, w] => Self::new_2d(w, h),
+            [c, h, w] => Self::new_3d(w, h, c),
+            _ => unreachable!("blobs have 1 to 3 axes"),
+        }
+    }
+
+    /// Resolves a possibly negative `axis` param.
+    fn axis(&self, axis: i32) -> std::result::Result<usize, String> {
+        let resolved = if axis < 0 { axis + self.dims } else { axis };
+        if (0..self.dims).contains(&resolved) {
+            Ok(resolved as usize)
+        } else {
+            Err(format!(
+                "axis {} is out of range for a {} input",
+                axis, self
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    /// Writes the sizes from the innermost axis, e.g. `224x224x3`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dims {
+            1 => write!(f, "{}", self.w),
+            2 => write!(f, "{}x{}", self.w, self.h),
+            _ => write!(f, "{}x{}x{}", self.w, self.h, self.c),
+        }
+    }
+}
+
+/// Inconsistency found by [Graph::infer_shapes].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShapeMismatch {
+    /// Index into [Graph::layers] of the layer at fault.
+    pub layer: usize,
+    /// 1-based line of the layer in the source text, if parsed from one.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ShapeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Blob shapes computed by [Graph::infer_shapes].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InferredShapes {
+    /// Shape of each blob, by index into [Graph::blobs], `None` when it could not be inferred.
+    pub blobs: Vec<Option<Shape>>,
+    pub mismatches: Vec<ShapeMismatch>,
+}
+
+impl InferredShapes {
+    /// Returns the shape of a blob, if inferred.
+    pub fn get(&self, blob: usize) -> Option<Shape> {
+        self.blobs.get(blob).copied().flatten()
+    }
+
+    /// Fails with the first mismatch found, if any.
+    pub fn check(&self) -> Result<()> {
+        match self.mismatches.first() {
+            Some(mismatch) => Err(Error::ShapeMismatch(mismatch.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Graph {
+    /// Computes the shape of every blob from the shapes of the input blobs.
+    ///
+    /// Inputs missing from `inputs` take the shape declared by their `Input` layer. Shapes of
+    /// layer types without inference come from the shape hints of the params, if present and
+    /// computed for the same input shapes. Layers which cannot accept their inputs are reported
+    /// as mismatches, as are inputs differing from the declared shape and inferred shapes
+    /// differing from the hints. Fails if an input name is not produced by an `Input` layer.
+    pub fn infer_shapes(&self, inputs: &[(&str, Shape)]) -> Result<InferredShapes> {
+        let input_blobs = self.input_blobs();
+        let mut given = vec![None; self.blobs.len()];
+        for (name, shape) in inputs {
+            let blob = input_blobs
+                .iter()
+                .copied()
+                .find(|&b| self.blobs[b].name == *name)
+                .ok_or_else(|| Error::UnknownBlob(name.to_string()))?;
+            given[blob] = Some(*shape);
+        }
+
+        // Hints only describe the input shapes they were computed for.
+        let use_hints = self
+            .layers
+            .iter()
+            .filter(|l| l.type_name == "Input")
+            .all(|l| match shape_hints(l) {
+                Some(hints) => l
+                    .tops
+                    .iter()
+                    .zip(hints)
+                    .all(|(&top, hint)| given[top].is_none_or(|shape| shape == hint)),
+                None => true,
+            });
+
+        let mut shapes = InferredShapes {
+            blobs: vec![None; self.blobs.len()],
+            mismatches: Vec::new(),
+        };
+        for (index, layer) in self.layers.iter().enumerate() {
+            let mut mismatch = |message: String| {
+                shapes.mismatches.push(ShapeMismatch {
+                    layer: index,
+                    line: layer.line,
+                    message: format!("Layer `{}`: {}", layer.name, message),
+                })
+            };
+
+            let bottoms: Option<Vec<Shape>> =
+                layer.bottoms.iter().map(|&b| shapes.blobs[b]).collect();
+            let inferred = if layer.type_name == "Input" {
+                let declared = match InputParams::from_params(&layer.params) {
+                    Ok(p) if p.d > 0 => {
+                        mismatch(UNSUPPORTED_4D.into());
+                        None
+                    }
+                    Ok(p) => Shape::declared(p.w, p.h, p.c),
+                    Err(_) => None,
+                };
+                let tops: Option<Vec<Shape>> = layer
+                    .tops
+                    .iter()
+                    .map(|&top| given[top].or(declared))
+                    .collect();
+                for &top in &layer.tops {
+                    if let (Some(shape), Some(declared)) = (given[top], declared) {
+                        if shape != declared {
+                            mismatch(format!(
+                                "input `{}` is {}, the network expects {}",
+                                self.blobs[top].name, shape, declared
+                            ));
+                        }
+                    }
+                }
+                tops
+            } else {
+                match bottoms.map(|bottoms| infer_layer(layer, &bottoms)) {
+                    Some(Ok(Some(tops))) if tops.len() == layer.tops.len() => Some(tops),
+                    Some(Ok(Some(tops))) => {
+                        mismatch(format!(
+                            "produces {} blobs, not {}",
+                            tops.len(),
+                            layer.tops.len()
+                        ));
+                        None
+                    }
+                    Some(Err(message)) => {
+                        mismatch(message);
+                        None
+                    }
+                    Some(Ok(None)) | None => None,
+                }
+            };
+
+            let hints = match shape_hints(layer).filter(|_| use_hints) {
+                Some(hints) if hints.iter().any(|h| h.dims > 3) => {
+                    mismatch(UNSUPPORTED_4D.into());
+                    None
+                }
+                hints => hints,
+            };
+            match (inferred, hints) {
+                (Some(tops), hints) => {
+                    for (i, (&top, shape)) in layer.tops.iter().zip(&tops).enumerate() {
+                        if let Some(hint) = hints.as_ref().map(|h| h[i]) {
+                            if hint != *shape {
+                                mismatch(format!(
+                                    "blob `{}` is {}, the params hint {}",
+                                    self.blobs[top].name, shape, hint
+                                ));
+                            }
+                        }
+                        shapes.blobs[top] = Some(*shape);
+                    }
+                }
+                (None, Some(hints)) => {
+                    for (&top, hint) in layer.tops.iter().zip(hints) {
+                        shapes.blobs[top] = Some(hint);
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+        Ok(shapes)
+    }
+}
+
+/// Reads the shape hints of a layer, one per top blob.
+///
+/// Hints of 4D blobs take a fifth value for the depth, it is skipped and their dims are kept
+/// for [Graph::infer_shapes] to report them as unsupported.
+fn shape_hints(layer: &Layer) -> Option<Vec<Shape>> {
+    let values = layer.params.get_int_array(SHAPE_HINT_ID)?;
+    let stride = [4, 5]
+        .into_iter()
+        .find(|stride| values.len() == layer.tops.len() * stride)?;
+    Some(
+        values
+            .chunks(stride)
+            .map(|v| Shape {
+                dims: v[0],
+                w: v[1],
+                h: v[2],
+                c: v[stride - 1],
+            })
+            .collect(),
+    )
+}
+
+type Inference = std::result::Result<Option<Vec<Shape>>, String>;
+
+/// Computes the top shapes of a layer, `None` for layer types without inference.
+fn infer_layer(layer: &Layer, bottoms: &[Shape]) -> Inference {
+    let params = |e: Error| e.to_string();
+    let first = match bottoms.first() {
+        Some(first) => *first,
+        None if layer.type_name == "MemoryData" => {
+            let p = &layer.params;
+            let shape = Shape::declared(p.get_int(0, 0), p.get_int(1, 0), p.get_int(2, 0));
+            return Ok(shape.map(|shape| vec![shape]));
+        }
+        None => return Ok(None),
+    };
+    let same = Ok(Some(vec![first; layer.tops.len()]));
+
+    match layer.type_name.as_str() {
+        "AbsVal" | "BNLL" | "Bias" | "Clip" | "Dropout" | "ELU" | "Exp" | "GELU"
+        | "HardSigmoid" | "HardSwish" | "LRN" | "Log" | "MVN" | "Mish" | "Noop" | "Normalize"
+        | "Power" | "PReLU" | "ReLU" | "SELU" | "Scale" | "ShuffleChannel" | "Sigmoid"
+        | "Softmax" | "Softplus" | "Split" | "Swish" | "TanH" | "Threshold" | "UnaryOp" => same,
+        "BatchNorm" => {
+            // Channels are the outermost axis, whatever the dimensions.
+            let (channels, actual) = (layer.params.get_int(0, 0), first.axes()[0]);
+            if channels != actual {
+                return Err(format!("has {} channels, the input {}", channels, actual));
+            }
+            same
+        }
+        "Convolution" | "ConvolutionDepthWise" => {
+            // Convolution reads the ids of ConvolutionDepthWise but the group, left at 1.
+            let p = ConvolutionDepthWiseParams::from_params(&layer.params).map_err(params)?;
+            convolution(&p, first)
+        }
+        "Convolution3D"
+        | "ConvolutionDepthWise3D"
+        | "Deconvolution3D"
+        | "DeconvolutionDepthWise3D"
+        | "Pooling3D" => Err(UNSUPPORTED_4D.into()),
+        "Deconvolution" | "DeconvolutionDepthWise" => {
+            // DeconvolutionDepthWise adds the group to the ids of Deconvolution.
+            let p = DeconvolutionParams::from_params(&layer.params).map_err(params)?;
+            deconvolution(&p, first)
+        }
+        "Pooling" => {
+            let p = PoolingParams::from_params(&layer.params).map_err(params)?;
+            pooling(&p, first)
+        }
+        "InnerProduct" => {
+            let p = InnerProductParams::from_params(&layer.params).map_err(params)?;
+            let num_input = match p.num_output {
+                0 => 0,
+                num_output => p.weight_data_size / num_output,
+            };
+            if first.dims == 2 && first.w == num_input {
+                return Ok(Some(vec![Shape::new_2d(p.num_output, first.h)]));
+            }
+            if num_input != 0 && first.size() != num_input as i64 {
+                return Err(format!("expects {} inputs, got {}", num_input, first));
+            }
+            Ok(Some(vec![Shape::new_1d(p.num_output)]))
+        }
+        "Flatten" => Ok(Some(vec![Shape::new_1d(first.size() as i32)])),
+        "Concat" => {
+            let p = ConcatParams::from_params(&layer.params).map_err(params)?;
+            let axis = first.axis(p.axis)?;
+            let mut axes = first.axes();
+            for bottom in &bottoms[1..] {
+                let other = bottom.axes();
+                let compatible = other.len() == axes.len()
+                    && (0..axes.len()).all(|i| i == axis || other[i] == axes[i]);
+                if !compatible {
+                    return Err(format!("cannot concatenate {} and {}", first, bottom));
+                }
+                axes[axis] = axis_size(axes[axis] as i64 + other[axis] as i64)?;
+            }
+            Ok(Some(vec![Shape::from_axes(&axes)]))
+        }
+        "Slice" => {
+            let p = SliceParams::from_params(&layer.params).map_err(params)?;
+            if p.slices.is_empty() {
+                return Ok(None);
+            }
+            let axis = first.axis(p.axis)?;
+            let axes = first.axes();
+            let mut offset = 0i64;
+            let mut tops = Vec::new();
+            for (i, &slice) in p.slices.iter().enumerate() {
+                let size = match slice {
+                    -233 => ((axes[axis] as i64 - offset) / (p.slices.len() - i) as i64) as i32,
+                    size => size,
+                };
+                offset += size as i64;
+                if size <= 0 || offset > axes[axis] as i64 {
+                    return Err(format!("slices {:?} do not fit {}", p.slices, first));
+                }
+                let mut top = axes.clone();
+                top[axis] = size;
+                tops.push(Shape::from_axes(&top));
+            }
+            Ok(Some(tops))
+        }
+        "Eltwise" => {
+            if let Some(other) = bottoms.iter().find(|b| **b != first) {
+                return Err(format!("cannot combine {} and {}", first, other));
+            }
+            same
+        }
+        "BinaryOp" => match bottoms {
+            [a, b] if layer.params.get_int(1, 0) == 0 => broadcast(*a, *b).map(|s| Some(vec![s])),
+            _ => same,
+        },
+        "Reshape" => {
+            let p = ReshapeParams::from_params(&layer.params).map_err(params)?;
+            reshape(&p, first)
+        }
+        "Interp" => {
+            let p = InterpParams::from_params(&layer.params).map_err(params)?;
+            if p.dynamic_target_size != 0 || bottoms.len() > 1 {
+                return Ok(None);
+            }
+            let scaled = |size: i32, output: i32, scale: f32| match output {
+                0 => (size as f32 * scale) as i32,
+                output => output,
+            };
+            let w = scaled(first.w, p.output_width, p.width_scale);
+            let h = scaled(first.h, p.output_height, p.height_scale);
+            Ok(Some(vec![match first.dims {
+                // Elements of 1D blobs are resized as channels.
+                1 => Shape::new_3d(w, h, first.w),
+                2 => Shape::new_2d(w, first.h),
+                _ => Shape::new_3d(w, h, first.c),
+            }]))
+        }
+        "Padding" => {
+            let p = PaddingParams::from_params(&layer.params).map_err(params)?;
+            let padded = |size: i32, before: i32, after: i32| {
+                axis_size(size as i64 + before as i64 + after as i64)
+            };
+            let w = padded(first.w, p.left, p.right)?;
+            Ok(Some(vec![match first.dims {
+                1 => Shape::new_1d(w),
+                2 => Shape::new_2d(w, padded(first.h, p.top, p.bottom)?),
+                _ => Shape::new_3d(
+                    w,
+                    padded(first.h, p.top, p.bottom)?,
+                    padded(first.c, p.front, p.behind)?,
+                ),
+            }]))
+        }
+        "Crop" if bottoms.len() == 1 => crop(layer, first).map(|s| Some(vec![s])),
+        "Permute" => {
+            let order = layer.params.get_int(0, 0);
+            let Shape { w, h, c, .. } = first;
+            let permuted = match (first.dims, order) {
+                (1, _) | (_, 0) => first,
+                (2, 1) => Shape::new_2d(h, w),
+                (3, 1) => Shape::new_3d(h, w, c),
+                (3, 2) => Shape::new_3d(w, c, h),
+                (3, 3) => Shape::new_3d(c, w, h),
+                (3, 4) => Shape::new_3d(h, c, w),
+                (3, 5) => Shape::new_3d(c, h, w),
+                _ => return Err(format!("unknown order {} for a {} input", order, first)),
+            };
+            Ok(Some(vec![permuted]))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Converts a size computed in `i64` from param values back to a blob axis.
+fn axis_size(size: i64) -> std::result::Result<i32, String> {
+    i32::try_from(size).map_err(|_| format!("size {} overflows", size))
+}
+
+/// Extent of a dilated kernel, computed in `i64` as params may overflow `i32`.
+fn kernel_extent(kernel: i32, dilation: i32) -> i64 {
+    dilation as i64 * (kernel as i64 - 1) + 1
+}
+
+/// Size of an axis after ncnn's convolution padding, -233 and -234 padding to keep
+/// `size / stride` outputs.
+fn conv_padded(size: i32, kernel_extent: i64, stride: i32, before: i32, after: i32) -> i64 {
+    let (size, stride) = (size as i64, stride as i64);
+    if before == -233 || before == -234 {
+        size + (kernel_extent + (size - 1) / stride * stride - size).max(0)
+    } else {
+        size + before.max(0) as i64 + after.max(0) as i64
+    }
+}
+
+fn convolution(p: &ConvolutionDepthWiseParams, bottom: Shape) -> Inference {
+    if p.dynamic_weight != 0 {
+        return Ok(None);
+    }
+    let group = p.group.max(1);
+    let channels = match bottom.dims {
+        // 1D inputs go through 1x1 kernels as an inner product.
+        1 if p.kernel_w == 1 && p.kernel_h == 1 => bottom.w,
+        3 => bottom.c,
+        _ => return Err(format!("cannot convolve a {} input", bottom)),
+    };
+    if channels % group != 0 {
+        return Err(format!(
+            "cannot split {} channels in {} groups",
+            channels, group
+        ));
+    }
+    let weights = [channels / group, p.kernel_w, p.kernel_h]
+        .iter()
+        .fold(p.num_output as i64, |product, &n| {
+            product.saturating_mul(n as i64)
+        });
+    if p.weight_data_size != 0 && weights != p.weight_data_size as i64 {
+        return Err(format!(
+            "has {} weights, a {} input needs {}",
+            p.weight_data_size, bottom, weights
+        ));
+    }
+    if bottom.dims == 1 {
+        return Ok(Some(vec![Shape::new_1d(p.num_output)]));
+    }
+
+    if p.stride_w <= 0 || p.stride_h <= 0 {
+        return Err(format!("invalid stride {}x{}", p.stride_w, p.stride_h));
+    }
+    let extent_w = kernel_extent(p.kernel_w, p.dilation_w);
+    let extent_h = kernel_extent(p.kernel_h, p.dilation_h);
+    let padded_w = conv_padded(bottom.w, extent_w, p.stride_w, p.pad_left, p.pad_right);
+    let padded_h = conv_padded(bottom.h, extent_h, p.stride_h, p.pad_top, p.pad_bottom);
+    if padded_w < extent_w || padded_h < extent_h {
+        return Err(format!(
+            "kernel {}x{} exceeds a {} input",
+            extent_w, extent_h, bottom
+        ));
+    }
+    Ok(Some(vec![Shape::new_3d(
+        axis_size((padded_w - extent_w) / p.stride_w as i64 + 1)?,
+        axis_size((padded_h - extent_h) / p.stride_h as i64 + 1)?,
+        p.num_output,
+    )]))
+}
+
+fn deconvolution(p: &DeconvolutionParams, bottom: Shape) -> Inference {
+    if bottom.dims != 3 {
+        return Err(format!("cannot deconvolve a {} input", bottom));
+    }
+
+    // Both products fit in i64, their sum may not.
+    let full = |size: i32, stride: i32, kernel: i32, dilation: i32, output_pad: i32| {
+        ((size as i64 - 1) * stride as i64)
+            .checked_add(kernel_extent(kernel, dilation) + output_pad as i64)
+            .ok_or_else(|| "output size overflows".to_string())
+    };
+    let full_w = full(
+        bottom.w,
+        p.stride_w,
+        p.kernel_w,
+        p.dilation_w,
+        p.output_pad_right,
+    )?;
+    let full_h = full(
+        bottom.h,
+        p.stride_h,
+        p.kernel_h,
+        p.dilation_h,
+        p.output_pad_bottom,
+    )?;
+    let (w, h) = if p.pad_left > 0 || p.pad_right > 0 || p.pad_top > 0 || p.pad_bottom > 0 {
+        (
+            full_w.saturating_sub(p.pad_left as i64 + p.pad_right as i64),
+            full_h.saturating_sub(p.pad_top as i64 + p.pad_bottom as i64),
+        )
+    } else if p.output_w > 0 && p.output_h > 0 {
+        (p.output_w as i64, p.output_h as i64)
+    } else if p.pad_left == -233 || p.pad_left == -234 {
+        (
+            bottom.w as i64 * p.stride_w as i64,
+            bottom.h as i64 * p.stride_h as i64,
+        )
+    } else {
+        (full_w, full_h)
+    };
+    Ok(Some(vec![Shape::new_3d(
+        axis_size(w)?,
+        axis_size(h)?,
+        p.num_output,
+    )]))
+}
+
+fn pooling(p: &PoolingParams, bottom: Shape) -> Inference {
+    if bottom.dims != 3 {
+        return Err(format!("cannot pool a {} input", bottom));
+    }
+    if p.global_pooling != 0 {
+        return Ok(Some(vec![Shape::new_1d(bottom.c)]));
+    }
+    if p.adaptive_pooling != 0 {
+        let w = if p.out_w == -233 { bottom.w } else { p.out_w };
+        let h = if p.out_h == -233 { bottom.h } else { p.out_h };
+        return Ok(Some(vec![Shape::new_3d(w, h, bottom.c)]));
+    }
+
+    if p.stride_w <= 0 || p.stride_h <= 0 {
+        return Err(format!("invalid stride {}x{}", p.stride_w, p.stride_h));
+    }
+    let padded = |size: i32, kernel: i32, stride: i32, before: i32, after: i32| {
+        let (size, kernel, stride) = (size as i64, kernel as i64, stride as i64);
+        let padded = size + before as i64 + after as i64;
+        match p.pad_mode {
+            // Full padding rounds the output up.
+            0 => {
+                let tail = (padded - kernel) % stride;
+                padded + if tail != 0 { stride - tail } else { 0 }
+            }
+            1 => padded,
+            _ => size + (kernel + (size - 1) / stride * stride - size).max(0),
+        }
+    };
+    let padded_w = padded(bottom.w, p.kernel_w, p.stride_w, p.pad_left, p.pad_right);
+    let padded_h = padded(bottom.h, p.kernel_h, p.stride_h, p.pad_top, p.pad_bottom);
+    if padded_w < p.kernel_w as i64 || padded_h < p.kernel_h as i64 {
+        return Err(format!(
+            "kernel {}x{} exceeds a {} input",
+            p.kernel_w, p.kernel_h, bottom
+        ));
+    }
+    Ok(Some(vec![Shape::new_3d(
+        axis_size((padded_w - p.kernel_w as i64) / p.stride_w as i64 + 1)?,
+        axis_size((padded_h - p.kernel_h as i64) / p.stride_h as i64 + 1)?,
+        bottom.c,
+    )]))
+}
+
+/// Shape of a binary operation, the lower dimensional operand being broadcast.
+fn broadcast(a: Shape, b: Shape) -> std::result::Result<Shape, String> {
+    if a.dims != b.dims {
+        return Ok(if a.dims > b.dims { a } else { b });
+    }
+    let (axes_a, axes_b) = (a.axes(), b.axes());
+    let mut axes = Vec::new();
+    for (&x, &y) in axes_a.iter().zip(&axes_b) {
+        axes.push(match (x, y) {
+            _ if x == y => x,
+            (1, y) => y,
+            (x, 1) => x,
+            _ => return Err(format!("cannot broadcast {} and {}", a, b)),
+        });
+    }
+    Ok(Shape::from_axes(&axes))
+}
+
+fn reshape(p: &ReshapeParams, bottom: Shape) -> Inference {
+    if p.d != -233 {
+        return Err(UNSUPPORTED_4D.into());
+    }
+    // 0 copies the input size and -1 or an unset size takes the remaining elements.
+    let resolve = |size: i32, input: i32| match size {
+        0 => input,
+        -233 => -1,
+        size => size,
+    };
+    let (w, h, c) = (
+        resolve(p.w, bottom.w),
+        resolve(p.h, bottom.h),
+        resolve(p.c, bottom.c),
+    );
+    let mut axes = if p.c != -233 {
+        vec![c, h, w]
+    } else if p.h != -233 {
+        vec![h, w]
+    } else {
+        vec![w]
+    };
+    let requested = axes.clone();
+    let known = axes
+        .iter()
+        .filter(|&&s| s != -1)
+        .fold(1i64, |product, &s| product.saturating_mul(s as i64));
+    let total = bottom.size();
+    match axes.iter().filter(|&&s| s == -1).count() {
+        0 if known == total => {}
+        1 if known != 0 && total % known == 0 => {
+            let inferred = (total / known) as i32;
+            axes.iter_mut()
+                .filter(|s| **s == -1)
+                .for_each(|s| *s = inferred);
+        }
+        _ => return Err(format!("cannot reshape {} to {:?}", bottom, requested)),
+    }
+    Ok(Some(vec![Shape::from_axes(&axes)]))
+}
+
+fn crop(layer: &Layer, bottom: Shape) -> std::result::Result<Shape, String> {
+    let p = &layer.params;
+    let mut axes = bottom.axes();
+    if let Some(starts) = p.get_int_array(-23309) {
+        let ends = p.get_int_array(-23310).unwrap_or_default();
+        let crop_axes = p
+            .get_int_array(-23311)
+            .unwrap_or_else(|| (0..starts.len() as i32).collect());
+        for ((&start, &end), &axis) in starts.iter().zip(&ends).zip(&crop_axes) {
+            let axis = bottom.axis(axis)?;
+            let size = axes[axis];
+            let start = if start < 0 { size + start } else { start }.clamp(0, size);
+            let end = match end {
+                -233 => size,
+                end if end <= 0 => size + end,
+                end => end.min(size),
+            };
+            axes[axis] = end - start;
+        }
+    } else {
+        // Offsets before and after the kept part and kept size per axis, 0 or -233 keeping the
+        // rest of the axis.
+        let ids = [(2, 8, 5), (1, 7, 4), (0, 6, 3)];
+        let skip = 3 - axes.len();
+        for (size, &(before, after, kept)) in axes.iter_mut().zip(&ids[skip..]) {
+            let rest = *size as i64 - p.get_int(before, 0) as i64 - p.get_int(after, 0) as i64;
+            *size = axis_size(match p.get_int(kept, 0) {
+                0 | -233 => rest,
+                kept => rest.min(kept as i64),
+            })?;
+        }
+    }
+    if axes.iter().any(|&size| size <= 0) {
+        return Err(format!("crops all of a {} input", bottom));
+    }
+    Ok(Shape::from_axes(&axes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::{shipped_params, PARAMS_DIR};
+    use std::path::Path;
+
+    #[test]
+    fn agree_with_hints() {
+        let mut unhinted = Vec::new();
+        for path in shipped_params() {
+            let graph = Graph::from_path(&path).unwrap();
+            let shapes = graph.infer_shapes(&[]).unwrap();
+            assert_eq!(Vec::<ShapeMismatch>::new(), shapes.mismatches, "{:?}", path);
+            if graph.layers.iter().all(|l| shape_hints(l).is_none()) {
+                unhinted.push(path.file_name().unwrap().to_string_lossy().into_owned());
+            }
+        }
+        // Files written without hints, checked for inference errors only.
+        let expected = [
+            "blazeface.param",
+            "googlenet_int8.param",
+            "mobilenet_int8.param",
+            "mobilenet_ssd_int8.param",
+            "nanodet-plus-m_416-int8.param",
+            "nanodet-plus-m_416.param",
+            "resnet18_int8.param",
+            "resnet50_int8.param",
+            "squeezenet_int8.param",
+            "squeezenet_ssd_int8.param",
+            "vgg16_int8.param",
+        ];
+        assert_eq!(expected.as_slice(), unhinted);
+    }
+
+    #[test]
+    fn mobilenet_shapes() {
+        let graph = Graph::from_path(Path::new(PARAMS_DIR).join("mobilenet.param")).unwrap();
+        let output = graph.blob_index("output").unwrap();
+
+        let shapes = graph.infer_shapes(&[]).unwrap();
+        assert_eq!(Some(Shape::new_1d(1000)), shapes.get(output));
+
+        // Other input sizes ignore the hints and are reported against the declared size.
+        let shapes = graph
+            .infer_shapes(&[("data", Shape::new_3d(192, 192, 3))])
+            .unwrap();
+        assert_eq!(1, shapes.mismatches.len());
+        assert_eq!(
+            "line 3: Layer `data`: input `data` is 192x192x3, the network expects 224x224x3",
+            shapes.mismatches[0].to_string()
+        );
+        let pool = graph.blob_index("pool6").unwrap();
+        assert_eq!(Some(Shape::new_1d(1024)), shapes.get(pool));
+        assert!(shapes.check().is_err());
+
+        assert!(graph.infer_shapes(&[("conv1", Shape::new_1d(3))]).is_err());
+    }
+
+    #[test]
+    fn report_mismatches() {
+        let graph = Graph::parse(
+            "7767517\n4 5\nInput data 0 1 data 0=8 1=8 2=3\n\
+             Split split 1 2 data a b\n\
+             Convolution conv 1 1 a conv 0=4 1=3 4=1 6=108\n\
+             Eltwise sum 2 1 conv b sum 0=1\n",
+        )
+        .unwrap();
+        let shapes = graph.infer_shapes(&[]).unwrap();
+        let conv = graph.blob_index("conv").unwrap();
+        assert_eq!(Some(Shape::new_3d(8, 8, 4)), shapes.get(conv));
+        assert_eq!(None, shapes.get(graph.blob_index("sum").unwrap()));
+        assert_eq!(1, shapes.mismatches.len());
+        assert_eq!(3, shapes.mismatches[0].layer);
+        assert_eq!(Some(6), shapes.mismatches[0].line);
+
+        let shapes = graph
+            .infer_shapes(&[("data", Shape::new_3d(8, 8, 4))])
+            .unwrap();
+        assert!(shapes.mismatches[1].message.contains("has 108 weights"));
+    }
+
+    #[test]
+    fn unsupported_4d() {
+        let graph = Graph::parse(
+            "7767517\n4 4\nInput data 0 1 data 0=4 1=4 11=2 2=3\n\
+             Input hinted 0 1 hinted -23330=5,4,4,4,2,3\n\
+             Input flat 0 1 flat 0=16\n\
+             Reshape cube 1 1 flat cube 0=2 1=2 11=2 2=2\n",
+        )
+        .unwrap();
+        let shapes = graph.infer_shapes(&[]).unwrap();
+        let layers: Vec<_> = shapes.mismatches.iter().map(|m| m.layer).collect();
+        assert_eq!(vec![0, 1, 3], layers);
+        assert!(shapes
+            .mismatches
+            .iter()
+            .all(|m| m.message.ends_with(UNSUPPORTED_4D)));
+        assert_eq!(None, shapes.get(graph.blob_index("data").unwrap()));
+        assert_eq!(None, shapes.get(graph.blob_index("hinted").unwrap()));
+    }
+
+    #[test]
+    fn overflowing_params() {
+        let graph = Graph::parse(
+            "7767517\n4 4\nInput data 0 1 data 0=8 1=8 2=3\n\
+             Convolution conv 1 1 data conv 0=4 1=65536 11=65536 2=65536\n\
+             Deconvolution deconv 1 1 data deconv 0=4 1=65536 2=65536 3=65536\n\
+             Padding pad 1 1 data pad 0=2147483647 2=2147483647\n",
+        )
+        .unwrap();
+        let shapes = graph.infer_shapes(&[]).unwrap();
+        let layers: Vec<_> = shapes.mismatches.iter().map(|m| m.layer).collect();
+        assert_eq!(vec![1, 2, 3], layers);
+        assert!(shapes.mismatches[2].message.contains("overflows"));
+    }
+}
@@ -0,0 +1,319 @@
+//! Compute and memory estimates of parsed graphs.
+
+use super::{Graph, Layer, Shape};
+use crate::error::Result;
+
+/// Storage precision of convolution and inner product weights.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightPrecision {
+    Float32,
+    Float16,
+    Int8,
+}
+
+impl WeightPrecision {
+    /// Bytes per weight.
+    pub fn bytes(self) -> u64 {
+        match self {
+            WeightPrecision::Float32 => 4,
+            WeightPrecision::Float16 => 2,
+            WeightPrecision::Int8 => 1,
+        }
+    }
+}
+
+/// Estimates of a single layer, see [Graph::model_stats].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LayerStats {
+    /// Index into [Graph::layers].
+    pub layer: usize,
+    /// Multiply-accumulates of convolutions and inner products, 0 for other layer types and
+    /// `None` when the shapes needed are unknown.
+    pub macs: Option<u64>,
+    /// Convolution and inner product weights, stored at the precision of the model.
+    pub weights: u64,
+    /// Biases, normalization and other parameters, stored as float32 at any precision.
+    pub fp32_params: u64,
+}
+
+impl LayerStats {
+    /// Number of parameters, weights included.
+    pub fn params(&self) -> u64 {
+        self.weights + self.fp32_params
+    }
+
+    /// Size of the parameters in the model file.
+    pub fn weight_bytes(&self, precision: WeightPrecision) -> u64 {
+        self.weights * precision.bytes() + self.fp32_params * 4
+    }
+}
+
+/// Estimates of a whole network, see [Graph::model_stats].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModelStats {
+    pub layers: Vec<LayerStats>,
+    /// Largest size of the float32 blobs alive at once while running the layers in order, blobs
+    /// being released after their last consumer and in place layers reusing their input.
+    pub peak_activation_bytes: u64,
+    /// Blobs of unknown shape, left out of the estimates.
+    pub unknown_blobs: usize,
+}
+
+impl ModelStats {
+    /// Multiply-accumulates of the layers with a known count.
+    pub fn macs(&self) -> u64 {
+        self.layers
+            .iter()
+            .filter_map(|l| l.macs)
+            .fold(0, u64::saturating_add)
+    }
+
+    pub fn params(&self) -> u64 {
+        self.layers.iter().map(LayerStats::params).sum()
+    }
+
+    pub fn weight_bytes(&self, precision: WeightPrecision) -> u64 {
+        self.layers.iter().map(|l| l.weight_bytes(precision)).sum()
+    }
+}
+
+/// Layer types whose single output may reuse the memory of their single input.
+const IN_PLACE_TYPES: &[&str] = &[
+    "AbsVal",
+    "BNLL",
+    "BatchNorm",
+    "Bias",
+    "Clip",
+    "Dropout",
+    "ELU",
+    "Exp",
+    "GELU",
+    "HardSigmoid",
+    "HardSwish",
+    "Log",
+    "Mish",
+    "Noop",
+    "PReLU",
+    "Power",
+    "ReLU",
+    "SELU",
+    "Scale",
+    "Sigmoid",
+    "Softmax",
+    "Softplus",
+    "Swish",
+    "TanH",
+    "Threshold",
+    "UnaryOp",
+];
+
+impl Graph {
+    /// Estimates compute, parameter counts and memory of the network for the given input
+    /// shapes, missing inputs taking their declared shape as in [Graph::infer_shapes].
+    ///
+    /// Parameter counts come from the weight size params of each layer. Shape mismatches do
+    /// not fail the estimate, blobs they leave unknown are counted in
+    /// [ModelStats::unknown_blobs].
+    pub fn model_stats(&self, inputs: &[(&str, Shape)]) -> Result<ModelStats> {
+        let shapes = self.infer_shapes(inputs)?;
+        let layers = self
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(index, layer)| {
+                let shape = |blob: Option<&usize>| blob.and_then(|&b| shapes.get(b));
+                let (weights, fp32_params) = layer_params(layer);
+                LayerStats {
+                    layer: index,
+                    macs: layer_macs(
+                        layer,
+                        shape(layer.bottoms.first()),
+                        shape(layer.tops.first()),
+                    ),
+                    weights,
+                    fp32_params,
+                }
+            })
+            .collect();
+
+        Ok(ModelStats {
+            layers,
+            peak_activation_bytes: self.peak_activation_bytes(&shapes.blobs),
+            unknown_blobs: shapes.blobs.iter().filter(|s| s.is_none()).count(),
+        })
+    }
+
+    fn peak_activation_bytes(&self, shapes: &[Option<Shape>]) -> u64 {
+        // Last layer needing each blob, outputs staying alive to be extracted.
+        let last_use: Vec<usize> = self
+            .blobs
+            .iter()
+            .map(|b| {
+                b.consumers
+                    .iter()
+                    .max()
+                    .copied()
+                    .unwrap_or(self.layers.len())
+            })
+            .collect();
+
+        // Memory of each blob, shared by Split tops and in place outputs, alive from its
+        // producer to the last use of any blob sharing it.
+        let mut storage: Vec<Option<usize>> = vec![None; self.blobs.len()];
+        let mut lifetimes: Vec<(usize, usize, u64)> = Vec::new();
+        for (index, layer) in self.layers.iter().enumerate() {
+            let aliased = match (layer.bottoms.as_slice(), layer.tops.as_slice()) {
+                ([bottom], _) if layer.type_name == "Split" => storage[*bottom],
+                // Like ncnn, in place layers copy inputs whose memory is needed later.
+                ([bottom], [_]) if IN_PLACE_TYPES.contains(&layer.type_name.as_str()) => {
+                    storage[*bottom].filter(|&s| {
+                        (0..self.blobs.len())
+                            .all(|b| b == *bottom || storage[b] != Some(s) || last_use[b] <= index)
+                    })
+                }
+                _ => None,
+            };
+            for &top in &layer.tops {
+                let s = aliased.unwrap_or_else(|| {
+                    let size = shapes[top].map_or(0, |s| s.size().max(0) as u64);
+                    lifetimes.push((index, index, size.saturating_mul(4)));
+                    lifetimes.len() - 1
+                });
+                storage[top] = Some(s);
+                lifetimes[s].1 = lifetimes[s].1.max(last_use[top]);
+            }
+        }
+
+        (0..self.layers.len())
+            .map(|index| {
+                lifetimes
+                    .iter()
+                    .filter(|(start, end, _)| (*start..=*end).contains(&index))
+                    .map(|(_, _, bytes)| *bytes)
+                    .fold(0, u64::saturating_add)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Returns the weights and float32 parameters of a layer.
+fn layer_params(layer: &Layer) -> (u64, u64) {
+    let p = &layer.params;
+    let count = |id: i32| p.get_int(id, 0).max(0) as u64;
+    match layer.type_name.as_str() {
+        "Convolution" | "ConvolutionDepthWise" | "Deconvolution" | "DeconvolutionDepthWise" => {
+            (count(6), count(5) * count(0))
+        }
+        "InnerProduct" => (count(2), count(1) * count(0)),
+        "Embed" => (count(3), count(2) * count(0)),
+        // Slope, mean, variance and bias per channel.
+        "BatchNorm" => (0, 4 * count(0)),
+        "Scale" => (0, count(0) * (1 + count(1))),
+        "Bias" | "PReLU" => (0, count(0)),
+        "Normalize" => (0, count(3)),
+        "MemoryData" => {
+            let size = [0, 1, 11, 2]
+                .iter()
+                .fold(1, |size: u64, &id| size.saturating_mul(count(id).max(1)));
+            (0, size)
+        }
+        _ => (0, 0),
+    }
+}
+
+/// Returns the multiply-accumulates of a layer, each weight being used once per output pixel
+/// of convolutions, per input pixel of deconvolutions and per row of inner products.
+fn layer_macs(layer: &Layer, bottom: Option<Shape>, top: Option<Shape>) -> Option<u64> {
+    // Malformed params and shapes may be negative, they count as empty.
+    let count = |value: i32| value.max(0) as u64;
+    let weights = count(layer.params.get_int(
+        if layer.type_name == "InnerProduct" {
+            2
+        } else {
+            6
+        },
+        0,
+    ));
+    let pixels = |shape: Shape| match shape.dims {
+        3 => count(shape.w).saturating_mul(count(shape.h)),
+        _ => 1,
+    };
+    match layer.type_name.as_str() {
+        "Convolution" | "ConvolutionDepthWise" => Some(weights.saturating_mul(pixels(top?))),
+        "Deconvolution" | "DeconvolutionDepthWise" => Some(weights.saturating_mul(pixels(bottom?))),
+        "InnerProduct" => {
+            let top = top?;
+            Some(weights.saturating_mul(if top.dims == 2 { count(top.h) } else { 1 }))
+        }
+        _ => Some(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mobilenet_stats() {
+        let path = std::path::Path::new(crate::param::PARAMS_DIR).join("mobilenet.param");
+        let graph = Graph::from_path(path).unwrap();
+        let stats = graph.model_stats(&[]).unwrap();
+        assert_eq!(0, stats.unknown_blobs);
+
+        // MobileNet v1, batch norms folded into convolution biases.
+        assert_eq!(4_221_032, stats.params());
+        assert_eq!(568_740_352, stats.macs());
+        let conv1 = &stats.layers[1];
+        assert_eq!(Some(112 * 112 * 864), conv1.macs);
+        assert_eq!((864, 32), (conv1.weights, conv1.fp32_params));
+        assert_eq!(
+            stats.weight_bytes(WeightPrecision::Float32)
+                - stats.weight_bytes(WeightPrecision::Int8),
+            3 * stats.layers.iter().map(|l| l.weights).sum::<u64>()
+        );
+
+        // The input, the first convolution output and its in place activation.
+        assert!(stats.peak_activation_bytes >= 4 * (224 * 224 * 3 + 112 * 112 * 32));
+        let larger = graph
+            .model_stats(&[("data", Shape::new_3d(448, 448, 3))])
+            .unwrap();
+        assert_eq!(Some(4 * 112 * 112 * 864), larger.layers[1].macs);
+        assert_eq!(stats.params(), larger.params());
+    }
+
+    #[test]
+    fn activation_lifetimes() {
+        let graph = Graph::parse(
+            "7767517\n5 6\nInput data 0 1 data 0=10\n\
+             Split split 1 2 data a b\n\
+             ReLU relu 1 1 a relu\n\
+             Eltwise sum 2 1 relu b sum 0=1\n\
+             ReLU relu2 1 1 sum output\n",
+        )
+        .unwrap();
+        let stats = graph.model_stats(&[]).unwrap();
+        // The split shares the input, which the first activation copies as the sum still needs
+        // it, and the last activation runs in place.
+        assert_eq!(3 * 10 * 4, stats.peak_activation_bytes);
+        assert_eq!(0, stats.macs());
+    }
+
+    #[test]
+    fn malformed_params() {
+        let graph = Graph::parse(
+            "7767517\n3 3\nConvolution conv 0 1 conv 6=-1\n\
+             Deconvolution deconv 0 1 deconv 6=2147483647\n\
+             MemoryData memory 0 1 memory 0=2147483647 1=2147483647 11=2147483647 2=2147483647\n",
+        )
+        .unwrap();
+        let (conv, deconv) = (&graph.layers[0], &graph.layers[1]);
+        let shape = Shape::new_3d(8, 8, 1);
+        assert_eq!(Some(0), layer_macs(conv, None, Some(shape)));
+        let negative = Shape::new_3d(-8, 8, 1);
+        assert_eq!(Some(0), layer_macs(deconv, Some(negative), None));
+        let huge = Shape::new_3d(i32::MAX, i32::MAX, 1);
+        assert_eq!(Some(u64::MAX), layer_macs(deconv, Some(huge), None));
+        assert_eq!((0, u64::MAX), layer_params(&graph.layers[2]));
+    }
+}
@@ -0,0 +1,341 @@
+use super::{Blob, Graph, Layer, ParamMap, ParamValue, ARRAY_ID_OFFSET, MAGIC};
+use crate::error::{ensure, Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Same float detection as ncnn: a `.` or an exponent marks a float.
+fn is_float(value: &str) -> bool {
+    value.contains(['.', 'e', 'E'])
+}
+
+fn parse_scalar(value: &str) -> Result<ParamValue> {
+    if is_float(value) {
+        value
+            .parse::<f32>()
+            .map(ParamValue::Float)
+            .map_err(|_| Error::param(format!("Invalid float value `{}`", value)))
+    } else {
+        value
+            .parse::<i32>()
+            .map(ParamValue::Int)
+            .map_err(|_| Error::param(format!("Invalid int value `{}`", value)))
+    }
+}
+
+fn parse_array(value: &str) -> Result<ParamValue> {
+    let mut items = value.split(',');
+    let len = items
+        .next()
+        .and_then(|v| v.parse::<usize>().ok())
+        .ok_or_else(|| Error::param(format!("Invalid array length in `{}`", value)))?;
+    let items = items.collect::<Vec<_>>();
+    ensure!(
+        items.len() == len,
+        Error::param(format!(
+            "Expected {} array items, found {} in `{}`",
+            len,
+            items.len(),
+            value
+        ))
+    );
+
+    if items.iter().any(|v| is_float(v)) {
+        items
+            .iter()
+            .map(|v| {
+                v.parse::<f32>()
+                    .map_err(|_| Error::param(format!("Invalid float value `{}`", v)))
+            })
+            .collect::<Result<_>>()
+            .map(ParamValue::FloatArray)
+    } else {
+        items
+            .iter()
+            .map(|v| {
+                v.parse::<i32>()
+                    .map_err(|_| Error::param(format!("Invalid int value `{}`", v)))
+            })
+            .collect::<Result<_>>()
+            .map(ParamValue::IntArray)
+    }
+}
+
+/// Parses a `id=value` token into the parameter map.
+fn parse_param(token: &str, params: &mut ParamMap) -> Result<()> {
+    let (id, value) = token
+        .split_once('=')
+        .ok_or_else(|| Error::param(format!("Expected `id=value`, found `{}`", token)))?;
+    let id = id
+        .parse::<i32>()
+        .map_err(|_| Error::param(format!("Invalid param id `{}`", id)))?;
+
+    let value = if id <= ARRAY_ID_OFFSET {
+        parse_array(value)?
+    } else {
+        parse_scalar(value)?
+    };
+    params.set(id, value);
+
+    Ok(())
+}
+
+fn parse_count(token: Option<&str>, name: &str) -> Result<usize> {
+    token
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Error::param(format!("Invalid {}", name)))
+}
+
+/// Resolves blob names the same way ncnn does: bottoms reuse the first blob with a matching
+/// name, tops always create a new blob.
+struct BlobTable {
+    blobs: Vec<Blob>,
+    by_name: HashMap<String, usize>,
+}
+
+impl BlobTable {
+    fn create(&mut self, name: &str) -> usize {
+        let index = self.blobs.len();
+        self.blobs.push(Blob {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        self.by_name.entry(name.to_string()).or_insert(index);
+        index
+    }
+
+    fn find_or_create(&mut self, name: &str) -> usize {
+        match self.by_name.get(name) {
+            Some(index) => *index,
+            None => self.create(name),
+        }
+    }
+}
+
+fn parse_layer(line: &str, layer_index: usize, blobs: &mut BlobTable) -> Result<Layer> {
+    let mut tokens = line.split_whitespace();
+    let type_name = tokens.next().unwrap().to_string();
+    let name = tokens
+        .next()
+        .ok_or_else(|| Error::param("Missing layer name"))?
+        .to_string();
+    let bottom_count = parse_count(tokens.next(), "bottom count")?;
+    let top_count = parse_count(tokens.next(), "top count")?;
+
+    let mut bottoms = Vec::with_capacity(bottom_count);
+    for _ in 0..bottom_count {
+        let blob = tokens
+            .next()
+            .ok_or_else(|| Error::param(format!("Expected {} bottom blobs", bottom_count)))?;
+        let index = blobs.find_or_create(blob);
+        blobs.blobs[index].consumers.push(layer_index);
+        bottoms.push(index);
+    }
+
+    let mut tops = Vec::with_capacity(top_count);
+    for _ in 0..top_count {
+        let blob = tokens
+            .next()
+            .ok_or_else(|| Error::param(format!("Expected {} top blobs", top_count)))?;
+        let index = blobs.create(blob);
+        blobs.blobs[index].producer = Some(layer_index);
+        tops.push(index);
+    }
+
+    let mut params = ParamMap::new();
+    for token in tokens {
+        parse_param(token, &mut params)?;
+    }
+
+    Ok(Layer {
+        type_name,
+        name,
+        bottoms,
+        tops,
+        params,
+        line: None,
+    })
+}
+
+pub(super) fn parse(text: &str) -> Result<Graph> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty());
+
+    let (line, magic) = lines
+        .next()
+        .ok_or_else(|| Error::param("Empty param file"))?;
+    ensure!(
+        magic.parse::<i32>().ok() == Some(MAGIC),
+        Error::param(format!("Expected magic {}, found `{}`", MAGIC, magic)).with_line(line)
+    );
+
+    let (line, counts) = lines
+        .next()
+        .ok_or_else(|| Error::param("Missing layer and blob counts"))?;
+    let mut tokens = counts.split_whitespace();
+    let declared_layer_count =
+        parse_count(tokens.next(), "layer count").map_err(|e| e.with_line(line))?;
+    let declared_blob_count =
+        parse_count(tokens.next(), "blob count").map_err(|e| e.with_line(line))?;
+
+    let mut blobs = BlobTable {
+        blobs: Vec::with_capacity(declared_blob_count),
+        by_name: HashMap::with_capacity(declared_blob_count),
+    };
+    // Like ncnn, stop at the declared layer count.
+    let mut layers = Vec::with_capacity(declared_layer_count);
+    for (line, text) in lines.by_ref().take(declared_layer_count) {
+        let mut layer =
+            parse_layer(text, layers.len(), &mut blobs).map_err(|e| e.with_line(line))?;
+        layer.line = Some(line);
+        layers.push(layer);
+    }
+    let surplus_lines = lines.map(|(line, _)| line).collect();
+
+    ensure!(
+        layers.len() == declared_layer_count,
+        Error::param(format!(
+            "Expected {} layers, found {}",
+            declared_layer_count,
+            layers.len()
+        ))
+    );
+
+    Ok(Graph {
+        layers,
+        blobs: blobs.blobs,
+        declared_layer_count,
+        declared_blob_count,
+        surplus_lines,
+    })
+}
+
+/// Formats a float with the shortest digits that parse back to the same value, always with a
+/// `.` or an exponent so that ncnn reads it as a float, e.g. `0.1`, `300.0` or `1e-10`.
+fn write_float(f: &mut fmt::Formatter<'_>, value: f32) -> fmt::Result {
+    write!(f, "{:?}", value)
+}
+
+fn write_value(f: &mut fmt::Formatter<'_>, value: &ParamValue) -> fmt::Result {
+    match value {
+        ParamValue::Int(v) | ParamValue::Raw(v) => write!(f, "{}", v),
+        ParamValue::Float(v) => write_float(f, *v),
+        ParamValue::IntArray(v) | ParamValue::RawArray(v) => {
+            write!(f, "{}", v.len())?;
+            v.iter().try_for_each(|v| write!(f, ",{}", v))
+        }
+        ParamValue::FloatArray(v) => {
+            write!(f, "{}", v.len())?;
+            v.iter().try_for_each(|v| {
+                write!(f, ",")?;
+                write_float(f, *v)
+            })
+        }
+    }
+}
+
+pub(super) fn write(graph: &Graph, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{}", MAGIC)?;
+    writeln!(f, "{} {}", graph.layers.len(), graph.blobs.len())?;
+
+    for layer in &graph.layers {
+        write!(
+            f,
+            "{:<24} {:<24} {} {}",
+            layer.type_name,
+            layer.name,
+            layer.bottoms.len(),
+            layer.tops.len()
+        )?;
+        for blob in layer.bottoms.iter().chain(&layer.tops) {
+            write!(f, " {}", graph.blobs[*blob].name)?;
+        }
+        for (id, value) in layer.params.iter() {
+            write!(f, " {}=", id)?;
+            write_value(f, value)?;
+        }
+        writeln!(f)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        let mut params = ParamMap::new();
+        parse_param("0=32", &mut params).unwrap();
+        parse_param("3=-233", &mut params).unwrap();
+        parse_param("1=1.000000e-01", &mut params).unwrap();
+        parse_param("2=0.5", &mut params).unwrap();
+        parse_param("-23330=4,3,224,224,3", &mut params).unwrap();
+        parse_param("-23310=2,2.000000,3.0", &mut params).unwrap();
+
+        assert_eq!(Some(&ParamValue::Int(32)), params.get(0));
+        assert_eq!(Some(&ParamValue::Int(-233)), params.get(3));
+        assert_eq!(Some(&ParamValue::Float(0.1)), params.get(1));
+        assert_eq!(0.5, params.get_float(2, 0.0));
+        assert_eq!(Some(vec![3, 224, 224, 3]), params.get_int_array(-23330));
+        assert_eq!(Some(vec![2.0, 3.0]), params.get_float_array(-23310));
+
+        assert!(parse_param("0", &mut params).is_err());
+        assert!(parse_param("x=1", &mut params).is_err());
+        assert!(parse_param("-23330=3,1,2", &mut params).is_err());
+    }
+
+    #[test]
+    fn blob_resolution() {
+        let text = "7767517\n3 3\n\
+                    Input a 0 1 data\n\
+                    ReLU b 1 1 data x\n\
+                    ReLU c 1 1 data x\n";
+        let graph = parse(text).unwrap();
+        assert_eq!(3, graph.blobs.len());
+        assert_eq!(vec![1, 2], graph.blobs[0].consumers);
+        // Duplicated tops create distinct blobs with the same name.
+        assert_eq!("x", graph.blobs[1].name);
+        assert_eq!("x", graph.blobs[2].name);
+        assert_eq!(Some(1), graph.blob_index("x"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("123\n1 1\n").is_err());
+        assert!(parse("7767517\n2 1\nInput data 0 1 data\n").is_err());
+
+        // Lines past the declared layer count are not parsed, as ncnn ignores them.
+        let graph = parse("7767517\n1 1\nInput data 0 1 data\nReLU relu 1 1 data\n").unwrap();
+        assert_eq!(1, graph.layers.len());
+        assert_eq!(vec![4], graph.surplus_lines);
+
+        let err = parse("7767517\n1 1\n\nInput data 0 2 data\n").unwrap_err();
+        assert!(err.to_string().starts_with("line 4:"), "{}", err);
+    }
+
+    #[test]
+    fn float_format() {
+        struct F(f32);
+        impl fmt::Display for F {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write_float(f, self.0)
+            }
+        }
+        assert_eq!("0.1", F(0.1).to_string());
+        assert_eq!("300.0", F(300.0).to_string());
+        assert_eq!("-0.25", F(-0.25).to_string());
+        assert_eq!("0.0", F(0.0).to_string());
+        assert_eq!("1e-10", F(1e-10).to_string());
+
+        for value in [0.1, 1.0 / 3.0, 123456.8, -f32::MIN_POSITIVE, f32::MAX] {
+            let text = F(value).to_string();
+            assert!(is_float(&text), "{}", text);
+            assert_eq!(ParamValue::Float(value), parse_scalar(&text).unwrap());
+        }
+    }
+}
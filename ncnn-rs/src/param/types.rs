@@ -0,0 +1,137 @@
+/// Built-in layer types in ncnn type index order, as generated in `layer_type_enum.h`.
+const LAYER_TYPES: &[&str] = &[
+    "AbsVal",
+    "ArgMax",
+    "BatchNorm",
+    "Bias",
+    "BNLL",
+    "Concat",
+    "Convolution",
+    "Crop",
+    "Deconvolution",
+    "Dropout",
+    "Eltwise",
+    "ELU",
+    "Embed",
+    "Exp",
+    "Flatten",
+    "InnerProduct",
+    "Input",
+    "Log",
+    "LRN",
+    "MemoryData",
+    "MVN",
+    "Pooling",
+    "Power",
+    "PReLU",
+    "Proposal",
+    "Reduction",
+    "ReLU",
+    "Reshape",
+    "ROIPooling",
+    "Scale",
+    "Sigmoid",
+    "Slice",
+    "Softmax",
+    "Split",
+    "SPP",
+    "TanH",
+    "Threshold",
+    "Tile",
+    "RNN",
+    "LSTM",
+    "BinaryOp",
+    "UnaryOp",
+    "ConvolutionDepthWise",
+    "Padding",
+    "Squeeze",
+    "ExpandDims",
+    "Normalize",
+    "Permute",
+    "PriorBox",
+    "DetectionOutput",
+    "Interp",
+    "DeconvolutionDepthWise",
+    "ShuffleChannel",
+    "InstanceNorm",
+    "Clip",
+    "Reorg",
+    "YoloDetectionOutput",
+    "Quantize",
+    "Dequantize",
+    "Yolov3DetectionOutput",
+    "PSROIPooling",
+    "ROIAlign",
+    "Packing",
+    "Requantize",
+    "Cast",
+    "HardSigmoid",
+    "SELU",
+    "HardSwish",
+    "Noop",
+    "PixelShuffle",
+    "DeepCopy",
+    "Mish",
+    "StatisticsPooling",
+    "Swish",
+    "Gemm",
+    "GroupNorm",
+    "LayerNorm",
+    "Softplus",
+    "GRU",
+    "MultiHeadAttention",
+    "GELU",
+    "Convolution1D",
+    "Pooling1D",
+    "ConvolutionDepthWise1D",
+    "Convolution3D",
+    "ConvolutionDepthWise3D",
+    "Pooling3D",
+    "MatMul",
+    "Deconvolution1D",
+    "DeconvolutionDepthWise1D",
+    "Deconvolution3D",
+    "DeconvolutionDepthWise3D",
+    "Einsum",
+    "DeformableConv2D",
+    "GLU",
+    "Fold",
+    "Unfold",
+    "GridSample",
+    "CumulativeSum",
+    "CopyTo",
+    "Erf",
+    "Diag",
+];
+
+/// Returns the ncnn type index of a built-in layer type.
+pub fn layer_type_index(type_name: &str) -> Option<i32> {
+    LAYER_TYPES
+        .iter()
+        .position(|t| *t == type_name)
+        .map(|i| i as i32)
+}
+
+/// Returns the built-in layer type for an ncnn type index.
+pub fn layer_type_name(index: i32) -> Option<&'static str> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| LAYER_TYPES.get(i))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_index() {
+        assert_eq!(Some(6), layer_type_index("Convolution"));
+        assert_eq!(Some(16), layer_type_index("Input"));
+        assert_eq!(Some(42), layer_type_index("ConvolutionDepthWise"));
+        assert_eq!(None, layer_type_index("YoloV5Focus"));
+        assert_eq!(Some("Split"), layer_type_name(33));
+        assert_eq!(None, layer_type_name(-1));
+        assert_eq!(None, layer_type_name(1000));
+    }
+}
@@ -0,0 +1,341 @@
+//! Structural checks of parsed graphs.
+
+use super::Graph;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// Whether a [Diagnostic] prevents ncnn from running the network correctly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Kind of problem reported by a [Diagnostic].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// The header declares another layer or blob count than the params contain.
+    HeaderCount,
+    /// A blob is consumed but never produced, or consumed before being produced.
+    DanglingBlob,
+    /// A blob name is produced by several layers.
+    DuplicateBlob,
+    /// The layer type is neither built into ncnn nor registered.
+    UnknownLayerType,
+    /// A blob has several consumers without a `Split`, or a `Split` is malformed.
+    SplitMisuse,
+    /// A layer does not depend on any input of the network.
+    UnreachableLayer,
+}
+
+/// Problem found by [Graph::validate].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    /// Index into [Graph::layers] of the layer concerned, `None` for the header.
+    pub layer: Option<usize>,
+    /// 1-based line in the source text, if parsed from one.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
+
+impl Graph {
+    /// Checks the graph for problems ncnn reports as a bare load failure or not at all, layer
+    /// types being known if the linked ncnn creates them or they are listed in `custom_types`.
+    ///
+    /// Diagnostics are sorted by line.
+    pub fn validate(&self, custom_types: &[&str]) -> Vec<Diagnostic> {
+        let mut created = HashMap::new();
+        self.validate_with(|type_name| {
+            custom_types.contains(&type_name)
+                || *created
+                    .entry(type_name.to_string())
+                    .or_insert_with(|| crate::layer::Layer::create_by_type_name(type_name).is_ok())
+        })
+    }
+
+    /// Checks the graph as [Graph::validate] does, layer types being known if `is_known`
+    /// accepts them, e.g. [super::layer_type_index] to check without ncnn.
+    pub fn validate_with(&self, mut is_known: impl FnMut(&str) -> bool) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |severity, kind, layer: Option<usize>, message: String| {
+            // The header follows the magic number on the second line of text params.
+            let line = match layer {
+                Some(layer) => self.layers[layer].line,
+                None => self.layers.iter().find_map(|l| l.line).map(|_| 2),
+            };
+            diagnostics.push(Diagnostic {
+                severity,
+                kind,
+                layer,
+                line,
+                message,
+            });
+        };
+
+        // ncnn reads as many layers as declared and has room for as many blobs.
+        let layer_count = self.layers.len() + self.surplus_lines.len();
+        if self.declared_layer_count != layer_count {
+            report(
+                Severity::Error,
+                DiagnosticKind::HeaderCount,
+                None,
+                format!(
+                    "Header declares {} layers, found {}",
+                    self.declared_layer_count, layer_count
+                ),
+            );
+        }
+        if self.declared_blob_count < self.blobs.len() {
+            report(
+                Severity::Error,
+                DiagnosticKind::HeaderCount,
+                None,
+                format!(
+                    "Header declares {} blobs, found {}",
+                    self.declared_blob_count,
+                    self.blobs.len()
+                ),
+            );
+        } else if self.declared_blob_count > self.blobs.len() {
+            report(
+                Severity::Warning,
+                DiagnosticKind::HeaderCount,
+                None,
+                format!(
+                    "Header declares {} blobs, only {} are used",
+                    self.declared_blob_count,
+                    self.blobs.len()
+                ),
+            );
+        }
+
+        let mut checked_types = HashSet::new();
+        for (index, layer) in self.layers.iter().enumerate() {
+            if checked_types.insert(&layer.type_name) && !is_known(&layer.type_name) {
+                report(
+                    Severity::Error,
+                    DiagnosticKind::UnknownLayerType,
+                    Some(index),
+                    format!("Unknown layer type `{}`", layer.type_name),
+                );
+            }
+
+            if layer.type_name == "Split" {
+                if layer.bottoms.len() != 1 {
+                    report(
+                        Severity::Error,
+                        DiagnosticKind::SplitMisuse,
+                        Some(index),
+                        format!(
+                            "Split `{}` has {} bottom blobs instead of 1",
+                            layer.name,
+                            layer.bottoms.len()
+                        ),
+                    );
+                }
+                for &top in &layer.tops {
+                    if self.blobs[top].consumers.is_empty() {
+                        report(
+                            Severity::Warning,
+                            DiagnosticKind::SplitMisuse,
+                            Some(index),
+                            format!(
+                                "Split `{}` output `{}` is never consumed",
+                                layer.name, self.blobs[top].name
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut producers: HashMap<&str, usize> = HashMap::new();
+        for blob in &self.blobs {
+            let consumers = &blob.consumers;
+            match blob.producer {
+                Some(producer) => {
+                    if let Some(&first) = producers.get(blob.name.as_str()) {
+                        report(
+                            Severity::Error,
+                            DiagnosticKind::DuplicateBlob,
+                            Some(producer),
+                            format!(
+                                "Blob `{}` is already produced by `{}`",
+                                blob.name, self.layers[first].name
+                            ),
+                        );
+                    } else {
+                        producers.insert(&blob.name, producer);
+                    }
+                }
+                None => {
+                    // Bottoms only see the blobs produced before them.
+                    let later = self
+                        .blobs
+                        .iter()
+                        .filter(|b| b.name == blob.name)
+                        .find_map(|b| b.producer);
+                    let message = match later {
+                        Some(later) => format!(
+                            "Blob `{}` is consumed before `{}` produces it",
+                            blob.name, self.layers[later].name
+                        ),
+                        None => format!("Blob `{}` is never produced", blob.name),
+                    };
+                    report(
+                        Severity::Error,
+                        DiagnosticKind::DanglingBlob,
+                        consumers.first().copied(),
+                        message,
+                    );
+                }
+            }
+
+            // ncnn releases or overwrites blobs after their first consumer.
+            let mut layers = consumers.clone();
+            layers.dedup();
+            if layers.len() > 1 {
+                report(
+                    Severity::Error,
+                    DiagnosticKind::SplitMisuse,
+                    Some(layers[1]),
+                    format!(
+                        "Blob `{}` is consumed by {} layers without a Split",
+                        blob.name,
+                        layers.len()
+                    ),
+                );
+            }
+        }
+
+        // Layers run from the inputs, layers without bottoms being the sources.
+        let mut reached = vec![false; self.layers.len()];
+        let mut queue: VecDeque<usize> = (0..self.layers.len())
+            .filter(|&i| self.layers[i].bottoms.is_empty())
+            .collect();
+        for &source in &queue {
+            reached[source] = true;
+        }
+        while let Some(index) = queue.pop_front() {
+            for &top in &self.layers[index].tops {
+                for &consumer in &self.blobs[top].consumers {
+                    let ready = self.layers[consumer]
+                        .bottoms
+                        .iter()
+                        .all(|&b| self.blobs[b].producer.is_some_and(|p| reached[p]));
+                    if !reached[consumer] && ready {
+                        reached[consumer] = true;
+                        queue.push_back(consumer);
+                    }
+                }
+            }
+        }
+        for (index, layer) in self.layers.iter().enumerate() {
+            if !reached[index] {
+                report(
+                    Severity::Warning,
+                    DiagnosticKind::UnreachableLayer,
+                    Some(index),
+                    format!("Layer `{}` cannot be reached from the inputs", layer.name),
+                );
+            }
+        }
+
+        diagnostics.sort_by_key(|d| d.line);
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::layer_type_index;
+
+    fn validate(text: &str) -> Vec<Diagnostic> {
+        Graph::parse(text)
+            .unwrap()
+            .validate_with(|t| layer_type_index(t).is_some())
+    }
+
+    #[test]
+    fn shipped_params() {
+        for path in crate::param::shipped_params() {
+            let graph = Graph::from_path(&path).unwrap();
+            let diagnostics = graph.validate_with(|t| layer_type_index(t).is_some());
+            if path.ends_with("nanodet_m.param") {
+                let messages: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+                assert_eq!(
+                    vec!["line 2: error: Header declares 179 layers, found 180"],
+                    messages
+                );
+            } else {
+                assert_eq!(Vec::<Diagnostic>::new(), diagnostics, "{:?}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn blob_errors() {
+        let diagnostics = validate(
+            "7767517\n5 5\nInput data 0 1 data\n\
+             ReLU relu 1 1 conv relu\n\
+             Convolution conv 1 1 data conv 0=1 1=1 6=1\n\
+             Sigmoid sigmoid 1 1 relu conv\n\
+             Frobnicate frob 1 1 missing out\n",
+        );
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.line, d.severity, d.kind))
+            .collect();
+        assert_eq!(
+            vec![
+                (Some(2), Severity::Error, DiagnosticKind::HeaderCount),
+                (Some(4), Severity::Error, DiagnosticKind::DanglingBlob),
+                (Some(4), Severity::Warning, DiagnosticKind::UnreachableLayer),
+                (Some(6), Severity::Error, DiagnosticKind::DuplicateBlob),
+                (Some(6), Severity::Warning, DiagnosticKind::UnreachableLayer),
+                (Some(7), Severity::Error, DiagnosticKind::UnknownLayerType),
+                (Some(7), Severity::Error, DiagnosticKind::DanglingBlob),
+                (Some(7), Severity::Warning, DiagnosticKind::UnreachableLayer),
+            ],
+            found
+        );
+        assert_eq!(
+            "line 4: error: Blob `conv` is consumed before `conv` produces it",
+            diagnostics[1].to_string()
+        );
+    }
+
+    #[test]
+    fn split_misuse() {
+        let diagnostics = validate(
+            "7767517\n4 5\nInput data 0 1 data\n\
+             Split split 1 2 data a b\n\
+             ReLU relu 1 1 a relu\n\
+             Sigmoid sigmoid 1 1 a sigmoid\n",
+        );
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            vec![
+                "line 4: warning: Split `split` output `b` is never consumed",
+                "line 6: error: Blob `a` is consumed by 2 layers without a Split",
+            ],
+            messages
+        );
+    }
+}
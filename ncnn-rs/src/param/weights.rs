@@ -0,0 +1,224 @@
+//! Size of the model weights loaded by the layers of parsed graphs.
+
+use super::{
+    layer_type_index, BatchNormParams, ConvolutionDepthWiseParams, ConvolutionParams,
+    DeconvolutionParams, Graph, InnerProductParams, Layer, LayerParams, ScaleParams,
+};
+
+/// Tag of weights stored as float16.
+const FLOAT16_TAG: u32 = 0x01306B47;
+/// Tag of weights stored as int8.
+const INT8_TAG: u32 = 0x000D4B38;
+/// Tag of weights stored as float32 with extra scaling.
+const RAW_FLOAT32_TAG: u32 = 0x0002C056;
+/// Bytes of the float table preceding weights quantized to 8-bit indices.
+const QUANTIZE_TABLE_BYTES: usize = 256 * 4;
+
+/// Built-in layer types loading weights in a layout [layer_weights] does not describe.
+const UNSUPPORTED_TYPES: &[&str] = &[
+    "Convolution1D",
+    "Convolution3D",
+    "ConvolutionDepthWise1D",
+    "ConvolutionDepthWise3D",
+    "Deconvolution1D",
+    "Deconvolution3D",
+    "DeconvolutionDepthWise1D",
+    "DeconvolutionDepthWise3D",
+    "DeformableConv2D",
+    "Dequantize",
+    "Gemm",
+    "GroupNorm",
+    "GRU",
+    "InstanceNorm",
+    "LayerNorm",
+    "LSTM",
+    "MultiHeadAttention",
+    "Quantize",
+    "Requantize",
+    "RNN",
+];
+
+/// Weights loaded by a layer, as a number of elements.
+enum Weights {
+    /// Preceded by a tag giving their precision, as convolution and inner product weights.
+    Tagged(usize),
+    /// Stored as float32 without a tag.
+    Float32(usize),
+}
+
+impl Graph {
+    /// Bytes of `data` loaded as model weights by the layers, the precision of tagged weights
+    /// being read from `data` as ncnn does.
+    ///
+    /// Returns `None` when a layer loads weights in an unsupported layout, e.g. custom or int8
+    /// quantized layers.
+    pub(crate) fn model_size(&self, data: &[u8]) -> Option<usize> {
+        let mut size = 0usize;
+        for layer in &self.layers {
+            for weights in layer_weights(layer)? {
+                let bytes = match weights {
+                    Weights::Tagged(count) => tagged_size(data.get(size..).unwrap_or(&[]), count)?,
+                    Weights::Float32(count) => count.checked_mul(4)?,
+                };
+                size = size.checked_add(bytes)?;
+            }
+        }
+        Some(size)
+    }
+}
+
+/// Returns the weights `layer` loads in order, `None` if they are not known.
+fn layer_weights(layer: &Layer) -> Option<Vec<Weights>> {
+    // Malformed params may be negative, ncnn loading nothing for them.
+    let count = |value: i32| usize::try_from(value).unwrap_or(0);
+    let bias = |bias_term: i32, num_output: i32| {
+        (bias_term != 0).then(|| Weights::Float32(count(num_output)))
+    };
+    let p = &layer.params;
+
+    let weights = match layer.type_name.as_str() {
+        "Convolution" => {
+            let p = ConvolutionParams::from_params(p).ok()?;
+            if p.int8_scale_term != 0 {
+                return None;
+            }
+            if p.dynamic_weight != 0 {
+                return Some(Vec::new());
+            }
+            let weights = Weights::Tagged(count(p.weight_data_size));
+            vec![Some(weights), bias(p.bias_term, p.num_output)]
+        }
+        "ConvolutionDepthWise" => {
+            let p = ConvolutionDepthWiseParams::from_params(p).ok()?;
+            if p.int8_scale_term != 0 {
+                return None;
+            }
+            if p.dynamic_weight != 0 {
+                return Some(Vec::new());
+            }
+            let weights = Weights::Tagged(count(p.weight_data_size));
+            vec![Some(weights), bias(p.bias_term, p.num_output)]
+        }
+        "Deconvolution" | "DeconvolutionDepthWise" => {
+            // DeconvolutionDepthWise adds the group to the ids of Deconvolution.
+            let p = DeconvolutionParams::from_params(p).ok()?;
+            if p.dynamic_weight != 0 {
+                return Some(Vec::new());
+            }
+            let weights = Weights::Tagged(count(p.weight_data_size));
+            vec![Some(weights), bias(p.bias_term, p.num_output)]
+        }
+        "InnerProduct" => {
+            let p = InnerProductParams::from_params(p).ok()?;
+            if p.int8_scale_term != 0 {
+                return None;
+            }
+            let weights = Weights::Tagged(count(p.weight_data_size));
+            vec![Some(weights), bias(p.bias_term, p.num_output)]
+        }
+        // num_output, bias_term, weight_data_size and int8_scale_term.
+        "Embed" => {
+            if p.get_int(18, 0) != 0 {
+                return None;
+            }
+            let weights = Weights::Tagged(count(p.get_int(3, 0)));
+            vec![Some(weights), bias(p.get_int(2, 0), p.get_int(0, 0))]
+        }
+        // Slope, mean, variance and bias per channel.
+        "BatchNorm" => {
+            let p = BatchNormParams::from_params(p).ok()?;
+            (0..4)
+                .map(|_| Some(Weights::Float32(count(p.channels))))
+                .collect()
+        }
+        "Scale" => {
+            let p = ScaleParams::from_params(p).ok()?;
+            if p.scale_data_size == -233 {
+                return Some(Vec::new());
+            }
+            let scale = Weights::Float32(count(p.scale_data_size));
+            vec![Some(scale), bias(p.bias_term, p.scale_data_size)]
+        }
+        // bias_data_size, num_slope and scale_data_size.
+        "Bias" | "PReLU" => vec![Some(Weights::Float32(count(p.get_int(0, 0))))],
+        "Normalize" => vec![Some(Weights::Float32(count(p.get_int(3, 0))))],
+        // w, h, d and c, unused dimensions being 0.
+        "MemoryData" => {
+            let size = [0, 1, 11, 2].iter().try_fold(1usize, |size, &id| {
+                size.checked_mul(count(p.get_int(id, 0)).max(1))
+            })?;
+            vec![Some(Weights::Float32(size))]
+        }
+        t if UNSUPPORTED_TYPES.contains(&t) || layer_type_index(t).is_none() => return None,
+        _ => Vec::new(),
+    };
+    Some(weights.into_iter().flatten().collect())
+}
+
+/// Returns the size of `count` tagged weights at the start of `data`, tag included.
+fn tagged_size(data: &[u8], count: usize) -> Option<usize> {
+    let align = |size: usize| size.checked_add(3).map(|size| size & !3);
+    // Missing tags are counted as float32, reaching past the end of `data`.
+    let tag = data.get(..4).unwrap_or(&[0; 4]);
+    let flag: u32 = tag.iter().map(|b| *b as u32).sum();
+
+    let size = match u32::from_le_bytes(tag.try_into().ok()?) {
+        FLOAT16_TAG => align(count.checked_mul(2)?)?,
+        INT8_TAG => align(count)?,
+        RAW_FLOAT32_TAG => count.checked_mul(4)?,
+        _ if flag != 0 => QUANTIZE_TABLE_BYTES.checked_add(align(count)?)?,
+        _ => count.checked_mul(4)?,
+    };
+    size.checked_add(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(layers: &str) -> Graph {
+        let count = layers.lines().count() + 1;
+        Graph::parse(&format!(
+            "7767517\n{} {}\nInput data 0 1 data 0=4\n{}",
+            count, count, layers
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn weightless() {
+        let graph = parse("ReLU relu 1 1 data output\n");
+        assert_eq!(Some(0), graph.model_size(&[0; 8]));
+    }
+
+    #[test]
+    fn tagged_weights() {
+        let graph = parse("InnerProduct fc 1 1 data output 0=2 1=1 2=6\n");
+        let mut data = vec![0; 4 + 6 * 4 + 2 * 4];
+        assert_eq!(Some(data.len()), graph.model_size(&data));
+
+        data[..4].copy_from_slice(&FLOAT16_TAG.to_le_bytes());
+        assert_eq!(Some(4 + 12 + 2 * 4), graph.model_size(&data));
+        data[..4].copy_from_slice(&INT8_TAG.to_le_bytes());
+        assert_eq!(Some(4 + 8 + 2 * 4), graph.model_size(&data));
+        data[..4].copy_from_slice(&[1, 0, 0, 0]);
+        assert_eq!(Some(4 + 1024 + 8 + 2 * 4), graph.model_size(&data));
+
+        // A missing tag counts as float32.
+        assert_eq!(Some(4 + 6 * 4 + 2 * 4), graph.model_size(&[]));
+    }
+
+    #[test]
+    fn float32_weights() {
+        let graph = parse("BatchNorm bn 1 1 data bn 0=4\nScale scale 1 1 bn output 0=4 1=1\n");
+        assert_eq!(Some(4 * 4 * 4 + 2 * 4 * 4), graph.model_size(&[]));
+    }
+
+    #[test]
+    fn unsupported_weights() {
+        let graph = parse("LayerNorm ln 1 1 data output 0=4\n");
+        assert_eq!(None, graph.model_size(&[]));
+        let graph = parse("InnerProduct fc 1 1 data output 0=2 2=8 8=1\n");
+        assert_eq!(None, graph.model_size(&[]));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::allocator::Allocator;
+use crate::error::{ensure, Error, Result};
+use crate::mat::Mat;
+use crate::param::{ParamMap, ParamValue, ARRAY_ID_OFFSET};
+use core::mem::ManuallyDrop;
+use ncnn_bind::*;
+
+/// Number of parameter ids a [ParamDict] can hold, ids range from 0 to `MAX_PARAM_COUNT - 1`.
+pub const MAX_PARAM_COUNT: i32 = 32;
+
+/// Layer parameters as handed to ncnn layers, see [crate::Layer::load_param].
+///
+/// Arrays are stored as 1D matrices of 32 bit elements, read back with [Mat::as_slice] as `i32`
+/// or `f32` depending on the parameter.
+pub struct ParamDict {
+    ptr: ncnn_paramdict_t,
+    // Allocators of the arrays set, which the dictionary shares.
+    allocators: Vec<Allocator>,
+}
+
+unsafe impl Send for ParamDict {}
+
+impl ParamDict {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn check_id(id: i32) -> Result<()> {
+        ensure!(
+            (0..MAX_PARAM_COUNT).contains(&id),
+            Error::InvalidDimension(format!("Param id {} out of range", id))
+        );
+        Ok(())
+    }
+
+    /// Whether a value is set for `id`.
+    pub fn contains(&self, id: i32) -> bool {
+        Self::check_id(id).is_ok() && unsafe { ncnn_paramdict_get_type(self.ptr, id) } != 0
+    }
+
+    /// Returns an integer parameter, or `default` when missing.
+    pub fn get_int(&self, id: i32, default: i32) -> i32 {
+        if Self::check_id(id).is_err() {
+            return default;
+        }
+        unsafe { ncnn_paramdict_get_int(self.ptr, id, default) }
+    }
+
+    /// Returns a float parameter, or `default` when missing.
+    pub fn get_float(&self, id: i32, default: f32) -> f32 {
+        if Self::check_id(id).is_err() {
+            return default;
+        }
+        unsafe { ncnn_paramdict_get_float(self.ptr, id, default) }
+    }
+
+    /// Returns an array parameter, or `None` when missing or not an array.
+    ///
+    /// The array shares the data of the dictionary and keeps its allocators alive.
+    pub fn get_array(&self, id: i32) -> Option<Mat> {
+        Self::check_id(id).ok()?;
+        // 4 is an array of unknown element type, 5 an int array and 6 a float array.
+        if !matches!(unsafe { ncnn_paramdict_get_type(self.ptr, id) }, 4..=6) {
+            return None;
+        }
+        let default = Mat::new();
+        let mut array =
+            unsafe { Mat::from_raw(ncnn_paramdict_get_array(self.ptr, id, default.ptr())) };
+        array.keep_allocators(&self.allocators);
+        Some(array)
+    }
+
+    pub fn set_int(&mut self, id: i32, value: i32) -> Result<&mut Self> {
+        Self::check_id(id)?;
+        unsafe { ncnn_paramdict_set_int(self.ptr, id, value) };
+        Ok(self)
+    }
+
+    pub fn set_float(&mut self, id: i32, value: f32) -> Result<&mut Self> {
+        Self::check_id(id)?;
+        unsafe { ncnn_paramdict_set_float(self.ptr, id, value) };
+        Ok(self)
+    }
+
+    /// Sets an array parameter, `array` being a 1D matrix of 32 bit elements.
+    pub fn set_array(&mut self, id: i32, array: &Mat) -> Result<&mut Self> {
+        Self::check_id(id)?;
+        ensure!(
+            array.dimensions() == 1 && array.element_size() == 4,
+            Error::Layout("Param arrays must be 1D matrices of 32 bit elements".into())
+        );
+        unsafe { ncnn_paramdict_set_array(self.ptr, id, array.ptr()) };
+        self.allocators.extend(array.allocators().iter().cloned());
+        Ok(self)
+    }
+
+    /// Wraps parameters owned by ncnn, which must outlive the wrapper and never be dropped by it.
+    pub(crate) unsafe fn borrow_raw(ptr: ncnn_paramdict_t) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self {
+            ptr,
+            allocators: Vec::new(),
+        })
+    }
+
+    pub(crate) fn ptr(&self) -> ncnn_paramdict_t {
+        self.ptr
+    }
+}
+
+impl TryFrom<&ParamMap> for ParamDict {
+    type Error = Error;
+
+    fn try_from(map: &ParamMap) -> Result<Self> {
+        let mut pd = ParamDict::new();
+        for (id, value) in map.iter() {
+            // Arrays are keyed by their id in the text format, e.g. -23310 for id 10.
+            let array_id = ARRAY_ID_OFFSET.saturating_sub(id);
+            match value {
+                // ncnn reads raw bits set as integers as floats too.
+                ParamValue::Int(v) | ParamValue::Raw(v) => pd.set_int(id, *v)?,
+                ParamValue::Float(v) => pd.set_float(id, *v)?,
+                ParamValue::IntArray(v) | ParamValue::RawArray(v) => {
+                    let mut array = Mat::new_1d(v.len() as u32, None)?;
+                    array.as_mut_slice::<i32>()?.copy_from_slice(v);
+                    pd.set_array(array_id, &array)?
+                }
+                ParamValue::FloatArray(v) => {
+                    let mut array = Mat::new_1d(v.len() as u32, None)?;
+                    array.as_mut_slice::<f32>()?.copy_from_slice(v);
+                    pd.set_array(array_id, &array)?
+                }
+            };
+        }
+        Ok(pd)
+    }
+}
+
+impl Default for ParamDict {
+    fn default() -> Self {
+        Self {
+            ptr: unsafe { ncnn_paramdict_create() },
+            allocators: Vec::new(),
+        }
+    }
+}
+
+impl Drop for ParamDict {
+    fn drop(&mut self) {
+        unsafe {
+            ncnn_paramdict_destroy(self.ptr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_and_set() {
+        let mut pd = ParamDict::new();
+        pd.set_int(0, 3).unwrap().set_float(1, 0.5).unwrap();
+        assert_eq!(3, pd.get_int(0, 0));
+        assert_eq!(0.5, pd.get_float(1, 0.0));
+        assert_eq!(7, pd.get_int(2, 7));
+        assert!(!pd.contains(2));
+        assert!(pd.set_int(MAX_PARAM_COUNT, 1).is_err());
+        assert_eq!(1, pd.get_int(-1, 1));
+    }
+
+    #[test]
+    fn from_param_map() {
+        let mut map = ParamMap::new();
+        map.set(0, ParamValue::Int(4));
+        map.set(-23303, ParamValue::FloatArray(vec![0.5, 1.5]));
+        let pd = ParamDict::try_from(&map).unwrap();
+        assert_eq!(4, pd.get_int(0, 0));
+        let array = pd.get_array(3).unwrap();
+        assert_eq!(&[0.5, 1.5], array.as_slice::<f32>().unwrap());
+        assert!(pd.get_array(0).is_none());
+    }
+
+    #[test]
+    fn array_outlives_allocators() {
+        let alloc = Allocator::new();
+        let mut values = Mat::new_1d(2, Some(&alloc)).unwrap();
+        values.fill(2.0);
+        let mut pd = ParamDict::new();
+        pd.set_array(0, &values).unwrap();
+        drop((values, alloc));
+
+        let array = pd.get_array(0).unwrap();
+        drop(pd);
+        assert_eq!(&[2.0, 2.0], array.as_slice::<f32>().unwrap());
+    }
+}